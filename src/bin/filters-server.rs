@@ -0,0 +1,515 @@
+extern crate filters;
+
+use filters::bloom::BloomFilter;
+use filters::params::BloomParams;
+use filters::scalable::{ScalableBloomFilter, DEFAULT_GROWTH_FACTOR, DEFAULT_TIGHTENING_RATIO};
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+
+const DEFAULT_BIND: &str = "127.0.0.1:6379";
+/// What RedisBloom uses when `BF.ADD` or `BF.MADD` create a filter.
+const DEFAULT_ERROR_RATE: f64 = 0.01;
+const DEFAULT_CAPACITY: u64 = 100;
+/// Longest bulk string and array accepted from a client.
+const MAX_BULK_LEN: usize = 4 * 1024 * 1024;
+const MAX_ARRAY_LEN: usize = 64 * 1024;
+/// Largest capacity `BF.RESERVE` accepts, and the most bits one filter may grow to, so no
+/// client can make the server allocate more than this per filter.
+const MAX_CAPACITY: u64 = 1 << 30;
+const MAX_FILTER_BITS: u64 = 1 << 32;
+
+const USAGE: &str = "usage: filters-server [--bind ADDRESS]
+
+Serves the RedisBloom commands BF.RESERVE, BF.ADD, BF.MADD, BF.EXISTS, BF.MEXISTS and BF.INFO
+over RESP, keeping the filters in memory. Listens on 127.0.0.1:6379 by default. Capacities
+above 2^30 are refused, and no filter grows past 512 MiB.";
+
+/// A named filter: scaling like RedisBloom's default, or a single `BloomFilter` that refuses
+/// items past its capacity, like `NONSCALING`. A scaling filter also refuses items once its
+/// next stage would take it past `MAX_FILTER_BITS`.
+enum Filter {
+    Scaling { filter: ScalableBloomFilter, error_rate: f64, initial_capacity: u64, expansion: u64 },
+    NonScaling { filter: BloomFilter, capacity: u64, len: u64 },
+}
+
+impl Filter {
+    /// `None` if the filter's first stage alone would be larger than `MAX_FILTER_BITS`, or
+    /// its error rate too small to represent.
+    fn new(error_rate: f64, capacity: u64, expansion: Option<u64>) -> Option<Self> {
+        let first_stage_rate = match expansion {
+            Some(_) => Self::stage_error_rate(error_rate, 0),
+            None => error_rate,
+        };
+        if first_stage_rate <= 0.0 || Self::stage_bits(capacity, first_stage_rate) > MAX_FILTER_BITS {
+            return None;
+        }
+
+        Some(match expansion {
+            Some(expansion) => Filter::Scaling {
+                filter: ScalableBloomFilter::with_growth(rand::random(), error_rate, capacity,
+                                                         expansion, DEFAULT_TIGHTENING_RATIO),
+                error_rate,
+                initial_capacity: capacity,
+                expansion,
+            },
+            None => Filter::NonScaling { filter: BloomFilter::new(error_rate, capacity), capacity, len: 0 },
+        })
+    }
+
+    fn with_defaults() -> Self {
+        Filter::new(DEFAULT_ERROR_RATE, DEFAULT_CAPACITY, Some(DEFAULT_GROWTH_FACTOR)).unwrap()
+    }
+
+    /// Error rate `ScalableBloomFilter` gives its stage `index`.
+    fn stage_error_rate(error_rate: f64, index: usize) -> f64 {
+        error_rate * (1.0 - DEFAULT_TIGHTENING_RATIO) * DEFAULT_TIGHTENING_RATIO.powi(index as i32)
+    }
+
+    /// Bits of a stage, computed without allocating it.
+    fn stage_bits(capacity: u64, error_rate: f64) -> u64 {
+        BloomParams::for_items_and_fpr(capacity, error_rate).n_bits() as u64
+    }
+
+    /// Whether putting one more item would add a stage the memory limit has no room for,
+    /// or one whose error rate is too small to represent.
+    fn cannot_grow(filter: &ScalableBloomFilter, error_rate: f64, initial_capacity: u64, expansion: u64) -> bool {
+        if filter.len() < filter.capacity() {
+            return false;
+        }
+        let n_stages = filter.n_stages();
+        let capacity = (0..n_stages).fold(initial_capacity, |capacity, _| capacity.saturating_mul(expansion));
+        let rate = Self::stage_error_rate(error_rate, n_stages);
+        rate <= 0.0 || (filter.n_bits() as u64).saturating_add(Self::stage_bits(capacity, rate)) > MAX_FILTER_BITS
+    }
+
+    /// `Ok(false)` if the item was (possibly falsely) present already.
+    fn add(&mut self, item: &[u8]) -> Result<bool, &'static str> {
+        if self.contains(item) {
+            return Ok(false);
+        }
+        match self {
+            Filter::Scaling { filter, error_rate, initial_capacity, expansion } => {
+                if Self::cannot_grow(filter, *error_rate, *initial_capacity, *expansion) {
+                    return Err("ERR filter reached its memory limit");
+                }
+                filter.put(item);
+            }
+            Filter::NonScaling { filter, capacity, len } => {
+                if *len >= *capacity {
+                    return Err("ERR non scaling filter is full");
+                }
+                filter.put(item);
+                *len += 1;
+            }
+        }
+        Ok(true)
+    }
+
+    fn contains(&self, item: &[u8]) -> bool {
+        match self {
+            Filter::Scaling { filter, .. } => filter.contains(item),
+            Filter::NonScaling { filter, .. } => filter.contains(item),
+        }
+    }
+
+    fn info(&self) -> Reply {
+        let (capacity, n_bits, n_filters, len, expansion) = match self {
+            Filter::Scaling { filter, expansion, .. } =>
+                (filter.capacity(), filter.n_bits(), filter.n_stages(), filter.len(), Reply::Integer(*expansion as i64)),
+            Filter::NonScaling { filter, capacity, len } => (*capacity, filter.n_bits(), 1, *len, Reply::Nil),
+        };
+        Reply::Array(vec![
+            Reply::Status("Capacity"), Reply::Integer(capacity as i64),
+            Reply::Status("Size"), Reply::Integer(n_bits.div_ceil(8) as i64),
+            Reply::Status("Number of filters"), Reply::Integer(n_filters as i64),
+            Reply::Status("Number of items inserted"), Reply::Integer(len as i64),
+            Reply::Status("Expansion rate"), expansion,
+        ])
+    }
+}
+
+type Store = Arc<Mutex<HashMap<Vec<u8>, Filter>>>;
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Status(&'static str),
+    Error(String),
+    Integer(i64),
+    Nil,
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn error(message: &str) -> Self {
+        Reply::Error(message.to_string())
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Reply::Status(status) => write!(writer, "+{}\r\n", status),
+            Reply::Error(message) => write!(writer, "-{}\r\n", message),
+            Reply::Integer(n) => write!(writer, ":{}\r\n", n),
+            Reply::Nil => write!(writer, "$-1\r\n"),
+            Reply::Array(replies) => {
+                write!(writer, "*{}\r\n", replies.len())?;
+                replies.iter().try_for_each(|reply| reply.write_to(writer))
+            }
+        }
+    }
+}
+
+/// Reads one command, either a RESP array of bulk strings or an inline command as typed into
+/// telnet. `Ok(None)` at the end of the stream.
+fn read_command<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let line = match read_line(reader)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    if line.first() != Some(&b'*') {
+        let words = line.split(|byte| byte.is_ascii_whitespace()).filter(|word| !word.is_empty());
+        return Ok(Some(words.map(|word| word.to_vec()).collect()));
+    }
+
+    let n_args = parse_length(&line[1..], MAX_ARRAY_LEN)?;
+    let mut args = vec![];
+    for _ in 0..n_args {
+        let header = read_line(reader)?.ok_or_else(|| protocol_error("unexpected end of stream"))?;
+        if header.first() != Some(&b'$') {
+            return Err(protocol_error("expected '$'"));
+        }
+        let len = parse_length(&header[1..], MAX_BULK_LEN)?;
+        // Grows with the data actually received rather than trusting the announced length.
+        let mut arg = vec![];
+        reader.take(len as u64 + 2).read_to_end(&mut arg)?;
+        if arg.len() < len + 2 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "bulk string is truncated"));
+        }
+        if !arg.ends_with(b"\r\n") {
+            return Err(protocol_error("bulk string is not terminated by CRLF"));
+        }
+        arg.truncate(len);
+        args.push(arg);
+    }
+    Ok(Some(args))
+}
+
+/// A line without its line ending.
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut line = vec![];
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    while line.last() == Some(&b'\n') || line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_length(digits: &[u8], max: usize) -> io::Result<usize> {
+    std::str::from_utf8(digits).ok()
+        .and_then(|digits| digits.parse().ok())
+        .filter(|&len| len <= max)
+        .ok_or_else(|| protocol_error("invalid length"))
+}
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Protocol error: {}", message))
+}
+
+fn parse<T: std::str::FromStr>(arg: &[u8]) -> Option<T> {
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+fn wrong_arity(name: &str) -> Reply {
+    Reply::Error(format!("ERR wrong number of arguments for '{}' command", name.to_ascii_lowercase()))
+}
+
+fn execute(store: &Store, args: &[Vec<u8>]) -> Reply {
+    let name = String::from_utf8_lossy(&args[0]).into_owned();
+    let args = &args[1..];
+    // Nothing below panics, but a poisoned store is still consistent enough to keep serving.
+    let mut filters = store.lock().unwrap_or_else(PoisonError::into_inner);
+
+    match (name.to_ascii_uppercase().as_str(), args) {
+        ("PING", []) => Reply::Status("PONG"),
+        ("COMMAND", _) => Reply::Array(vec![]),
+        ("BF.RESERVE", [key, error_rate, capacity, options @ ..]) => {
+            let error_rate = match parse::<f64>(error_rate) {
+                Some(rate) if rate > 0.0 && rate < 1.0 => rate,
+                _ => return Reply::error("ERR (0 < error rate range < 1)"),
+            };
+            let capacity = match parse::<u64>(capacity) {
+                Some(capacity) if capacity > 0 => capacity,
+                _ => return Reply::error("ERR (capacity should be larger than 0)"),
+            };
+            if capacity > MAX_CAPACITY {
+                return Reply::error("ERR capacity is too large");
+            }
+            let (mut expansion, mut nonscaling) = (None, false);
+            let mut options = options.iter();
+            while let Some(option) = options.next() {
+                match option.to_ascii_uppercase().as_slice() {
+                    b"NONSCALING" => nonscaling = true,
+                    b"EXPANSION" => match options.next().and_then(|arg| parse::<u64>(arg)) {
+                        Some(rate) if rate > 0 => expansion = Some(rate),
+                        _ => return Reply::error("ERR (expansion should be greater or equal to 1)"),
+                    },
+                    _ => return Reply::error("ERR syntax error"),
+                }
+            }
+            if nonscaling && expansion.is_some() {
+                return Reply::error("ERR nonscaling filters cannot expand");
+            }
+            if filters.contains_key(key) {
+                return Reply::error("ERR item exists");
+            }
+            let expansion = if nonscaling { None } else { Some(expansion.unwrap_or(DEFAULT_GROWTH_FACTOR)) };
+            match Filter::new(error_rate, capacity, expansion) {
+                Some(filter) => {
+                    filters.insert(key.clone(), filter);
+                    Reply::Status("OK")
+                }
+                None => Reply::error("ERR filter would exceed the memory limit"),
+            }
+        }
+        ("BF.ADD", [key, item]) => {
+            let filter = filters.entry(key.clone())
+                .or_insert_with(Filter::with_defaults);
+            match filter.add(item) {
+                Ok(added) => Reply::Integer(added as i64),
+                Err(message) => Reply::error(message),
+            }
+        }
+        ("BF.MADD", [key, items @ ..]) if !items.is_empty() => {
+            let filter = filters.entry(key.clone())
+                .or_insert_with(Filter::with_defaults);
+            Reply::Array(items.iter().map(|item| match filter.add(item) {
+                Ok(added) => Reply::Integer(added as i64),
+                Err(message) => Reply::error(message),
+            }).collect())
+        }
+        ("BF.EXISTS", [key, item]) =>
+            Reply::Integer(filters.get(key).is_some_and(|filter| filter.contains(item)) as i64),
+        ("BF.MEXISTS", [key, items @ ..]) if !items.is_empty() => {
+            let filter = filters.get(key);
+            Reply::Array(items.iter()
+                .map(|item| Reply::Integer(filter.is_some_and(|filter| filter.contains(item)) as i64))
+                .collect())
+        }
+        ("BF.INFO", [key]) => filters.get(key).map_or_else(|| Reply::error("ERR not found"), Filter::info),
+        ("PING", _) | ("BF.RESERVE", _) | ("BF.ADD", _) | ("BF.MADD", _) | ("BF.EXISTS", _)
+        | ("BF.MEXISTS", _) | ("BF.INFO", _) => wrong_arity(&name),
+        _ => Reply::Error(format!("ERR unknown command '{}'", name)),
+    }
+}
+
+/// Answers commands until the client quits or disconnects. Replies are flushed once no
+/// further pipelined command is buffered.
+fn serve<R: BufRead, W: Write>(store: &Store, mut reader: R, mut writer: W) -> io::Result<()> {
+    loop {
+        let args = match read_command(&mut reader) {
+            Ok(Some(args)) => args,
+            Ok(None) => return writer.flush(),
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                Reply::Error(format!("ERR {}", err)).write_to(&mut writer)?;
+                return writer.flush();
+            }
+            Err(err) => return Err(err),
+        };
+        if args.is_empty() {
+            continue;
+        }
+
+        if args[0].eq_ignore_ascii_case(b"QUIT") {
+            Reply::Status("OK").write_to(&mut writer)?;
+            return writer.flush();
+        }
+        execute(store, &args).write_to(&mut writer)?;
+        if reader.fill_buf()?.is_empty() {
+            writer.flush()?;
+        }
+    }
+}
+
+fn handle(store: Store, stream: TcpStream) -> io::Result<()> {
+    let reader = BufReader::new(stream.try_clone()?);
+    serve(&store, reader, BufWriter::new(stream))
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut bind = DEFAULT_BIND.to_string();
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--bind", Some(address)) => bind = address,
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    let listener = TcpListener::bind(&bind).unwrap_or_else(|err| {
+        eprintln!("filters-server: cannot listen on {}: {}", bind, err);
+        process::exit(1);
+    });
+    eprintln!("filters-server: listening on {}", bind);
+
+    let store = Store::default();
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let store = store.clone();
+                thread::spawn(move || handle(store, stream));
+            }
+            Err(err) => eprintln!("filters-server: {}", err),
+        }
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    fn run(store: &Store, input: &str) -> String {
+        let mut output = vec![];
+        serve(store, Cursor::new(input.as_bytes()), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn command(args: &[&str]) -> String {
+        args.iter().fold(format!("*{}\r\n", args.len()), |command, arg| {
+            command + &format!("${}\r\n{}\r\n", arg.len(), arg)
+        })
+    }
+
+    #[test]
+    fn test_server_reads_resp_and_inline_commands() {
+        let mut input = Cursor::new(b"*2\r\n$6\r\nBF.ADD\r\n$4\r\na\r\nb\r\nBF.EXISTS  k  x\r\n".to_vec());
+        assert_eq!(read_command(&mut input).unwrap(), Some(vec![b"BF.ADD".to_vec(), b"a\r\nb".to_vec()]));
+        assert_eq!(read_command(&mut input).unwrap(), Some(vec![b"BF.EXISTS".to_vec(), b"k".to_vec(), b"x".to_vec()]));
+        assert_eq!(read_command(&mut input).unwrap(), None);
+
+        assert!(read_command(&mut Cursor::new(b"*1\r\n+PING\r\n".to_vec())).is_err());
+        assert!(read_command(&mut Cursor::new(b"*1\r\n$4\r\nPINGxx".to_vec())).is_err());
+        assert!(read_command(&mut Cursor::new(b"*-5\r\n".to_vec())).is_err());
+        assert!(read_command(&mut Cursor::new(b"*1\r\n$536870912\r\n".to_vec())).is_err());
+        assert!(read_command(&mut Cursor::new(b"*1\r\n$100\r\nPING\r\n".to_vec())).is_err());
+        assert!(read_command(&mut Cursor::new(b"*100000\r\n".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_server_bloom_commands() {
+        let store = Store::default();
+        let input = [
+            command(&["BF.RESERVE", "fruit", "0.001", "1000"]),
+            command(&["BF.RESERVE", "fruit", "0.01", "10"]),
+            command(&["BF.ADD", "fruit", "apple"]),
+            command(&["BF.ADD", "fruit", "apple"]),
+            command(&["BF.MADD", "fruit", "pear", "plum"]),
+            command(&["BF.EXISTS", "fruit", "pear"]),
+            command(&["BF.MEXISTS", "fruit", "plum", "kiwi"]),
+            command(&["BF.EXISTS", "missing", "pear"]),
+        ].concat();
+        assert_eq!(run(&store, &input), "+OK\r\n-ERR item exists\r\n:1\r\n:0\r\n*2\r\n:1\r\n:1\r\n:1\r\n*2\r\n:1\r\n:0\r\n:0\r\n");
+
+        let info = run(&store, &command(&["bf.info", "fruit"]));
+        assert!(info.starts_with("*10\r\n+Capacity\r\n:1000\r\n+Size\r\n:"), "{}", info);
+        assert!(info.ends_with("+Number of filters\r\n:1\r\n+Number of items inserted\r\n:3\r\n+Expansion rate\r\n:2\r\n"));
+        assert_eq!(run(&store, &command(&["BF.INFO", "missing"])), "-ERR not found\r\n");
+    }
+
+    #[test]
+    fn test_server_scaling_and_nonscaling_filters() {
+        let store = Store::default();
+        // Stages for 100, 200 and 400 items.
+        let adds: String = (0..350).map(|n| command(&["BF.ADD", "auto", &format!("item-{}", n)])).collect();
+        run(&store, &adds);
+        let info = run(&store, &command(&["BF.INFO", "auto"]));
+        assert!(info.starts_with("*10\r\n+Capacity\r\n:700\r\n"), "{}", info);
+        assert!(info.contains("+Number of filters\r\n:3\r\n"), "{}", info);
+
+        let input = [
+            command(&["BF.RESERVE", "fixed", "0.0001", "2", "NONSCALING"]),
+            command(&["BF.MADD", "fixed", "a", "b", "c"]),
+            command(&["BF.EXISTS", "fixed", "c"]),
+            command(&["BF.INFO", "fixed"]),
+        ].concat();
+        let output = run(&store, &input);
+        assert!(output.starts_with("+OK\r\n*3\r\n:1\r\n:1\r\n-ERR non scaling filter is full\r\n:0\r\n"), "{}", output);
+        assert!(output.ends_with("+Expansion rate\r\n$-1\r\n"), "{}", output);
+
+        let output = run(&store, &command(&["BF.RESERVE", "wide", "0.01", "10", "EXPANSION", "4"]));
+        assert_eq!(output, "+OK\r\n");
+        assert!(run(&store, &command(&["BF.INFO", "wide"])).ends_with("+Expansion rate\r\n:4\r\n"));
+
+        let input = [
+            command(&["BF.RESERVE", "huge", "0.01", "1", "EXPANSION", "1000000000000"]),
+            command(&["BF.MADD", "huge", "a", "b"]),
+        ].concat();
+        assert_eq!(run(&store, &input), "+OK\r\n*2\r\n:1\r\n-ERR filter reached its memory limit\r\n");
+    }
+
+    #[test]
+    fn test_server_errors() {
+        let store = Store::default();
+        let input = [
+            command(&["BF.RESERVE", "k", "1.5", "10"]),
+            command(&["BF.RESERVE", "k", "0.01", "0"]),
+            command(&["BF.RESERVE", "k", "0.01", "10", "EXPANSION", "0"]),
+            command(&["BF.RESERVE", "k", "0.01", "10", "BOGUS"]),
+            command(&["BF.RESERVE", "k", "0.0000001", "1000000000000"]),
+            command(&["BF.RESERVE", "k", "1e-300", "100000000"]),
+            command(&["BF.RESERVE", "k", "5e-324", "10"]),
+            command(&["BF.RESERVE", "k", "0.01", "10", "NONSCALING", "EXPANSION", "2"]),
+            command(&["BF.ADD", "k"]),
+            command(&["BF.MEXISTS", "k"]),
+            command(&["FLUSHALL"]),
+            "PING\r\n".to_string(),
+            command(&["QUIT"]),
+            command(&["PING"]),
+        ].concat();
+        assert_eq!(run(&store, &input), [
+            "-ERR (0 < error rate range < 1)\r\n",
+            "-ERR (capacity should be larger than 0)\r\n",
+            "-ERR (expansion should be greater or equal to 1)\r\n",
+            "-ERR syntax error\r\n",
+            "-ERR capacity is too large\r\n",
+            "-ERR filter would exceed the memory limit\r\n",
+            "-ERR filter would exceed the memory limit\r\n",
+            "-ERR nonscaling filters cannot expand\r\n",
+            "-ERR wrong number of arguments for 'bf.add' command\r\n",
+            "-ERR wrong number of arguments for 'bf.mexists' command\r\n",
+            "-ERR unknown command 'FLUSHALL'\r\n",
+            "+PONG\r\n",
+            "+OK\r\n",
+        ].concat());
+        assert!(store.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_server_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let store = Store::default();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle(store, stream).unwrap();
+        });
+
+        let mut client = TcpStream::connect(address).unwrap();
+        let input = [command(&["BF.ADD", "k", "x"]), command(&["BF.EXISTS", "k", "x"]), command(&["QUIT"])].concat();
+        client.write_all(input.as_bytes()).unwrap();
+        let mut output = String::new();
+        client.read_to_string(&mut output).unwrap();
+        assert_eq!(output, ":1\r\n:1\r\n+OK\r\n");
+    }
+}
@@ -0,0 +1,189 @@
+use crate::error::{Error, Result};
+use crate::fingerprint::{self, Fingerprint};
+use crate::hash;
+use crate::peeling;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+
+const MAX_ATTEMPTS: usize = 100;
+const MAX_SEGMENT_LENGTH: u32 = 1 << 18;
+
+pub type BinaryFuseFilter8 = BinaryFuseFilter<u8>;
+pub type BinaryFuseFilter16 = BinaryFuseFilter<u16>;
+pub type BinaryFuseFilter32 = BinaryFuseFilter<u32>;
+
+/// Immutable 3-wise binary fuse filter (Graf & Lemire, 2022). Like `XorFilter`, but the
+/// three slots of a key fall in consecutive small segments of one array, which lets the
+/// key graph peel at about `1.13 * F::BITS` bits per key for large sets.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "BinaryFuseFilterFields<F>"))]
+pub struct BinaryFuseFilter<F> {
+    seed: u64,
+    mix_seed: u64,
+    segment_length: u32,
+    segment_count_length: u32,
+    len: usize,
+    fingerprints: Vec<F>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct BinaryFuseFilterFields<F> {
+    seed: u64,
+    mix_seed: u64,
+    segment_length: u32,
+    segment_count_length: u32,
+    len: usize,
+    fingerprints: Vec<F>,
+}
+
+#[cfg(feature = "serde")]
+impl<F> TryFrom<BinaryFuseFilterFields<F>> for BinaryFuseFilter<F> {
+    type Error = Error;
+
+    fn try_from(fields: BinaryFuseFilterFields<F>) -> Result<Self> {
+        let BinaryFuseFilterFields { seed, mix_seed, segment_length, segment_count_length, len, fingerprints } = fields;
+        if !segment_length.is_power_of_two() || segment_count_length == 0
+            || segment_count_length % segment_length != 0 {
+            return Err(Error::Corrupt("segment lengths out of range"));
+        }
+        if fingerprints.len() as u64 != segment_count_length as u64 + 2 * segment_length as u64 {
+            return Err(Error::Corrupt("fingerprint count does not match the segments"));
+        }
+        Ok(BinaryFuseFilter { seed, mix_seed, segment_length, segment_count_length, len, fingerprints })
+    }
+}
+
+impl<F: Fingerprint> BinaryFuseFilter<F> {
+    pub fn build<I>(keys: I) -> Result<Self> where I: IntoIterator, I::Item: Hash {
+        BinaryFuseFilter::build_with_seed(rand::random(), keys)
+    }
+
+    /// Builds the filter, retrying with a fresh mixing seed whenever the key graph cannot
+    /// be peeled. Keys with equal hashes are only stored once.
+    pub fn build_with_seed<I>(seed: u64, keys: I) -> Result<Self> where I: IntoIterator, I::Item: Hash {
+        let mut hashes: Vec<u64> = keys.into_iter().map(|key| hash::hash128(&key, seed).0).collect();
+        hashes.sort_unstable();
+        hashes.dedup();
+        if hashes.len() > u32::MAX as usize / 2 {
+            return Err(Error::BuildFailed("too many keys"));
+        }
+
+        let (segment_length, segment_count) = Self::dimensions(hashes.len() as u32);
+        let n_slots = ((segment_count + 2) * segment_length) as usize;
+        let mut mix_seed = seed;
+
+        for _ in 0..MAX_ATTEMPTS {
+            mix_seed = fingerprint::mix64(mix_seed.wrapping_add(0x9e37_79b9_7f4a_7c15));
+            let mut filter = BinaryFuseFilter {
+                seed,
+                mix_seed,
+                segment_length,
+                segment_count_length: segment_count * segment_length,
+                len: hashes.len(),
+                fingerprints: vec![],
+            };
+            let mixed: Vec<u64> = hashes.iter().map(|&hash| filter.mix(hash)).collect();
+            if let Some(order) = peeling::peel(&mixed, n_slots, |hash| filter.slots(hash)) {
+                filter.fingerprints = peeling::assign(order, n_slots, |hash| filter.slots(hash));
+                return Ok(filter);
+            }
+        }
+        Err(Error::BuildFailed("key graph could not be peeled"))
+    }
+
+    pub fn contains<T: Hash>(&self, value: T) -> bool {
+        let hash = self.mix(hash::hash128(&value, self.seed).0);
+        let [h0, h1, h2] = self.slots(hash);
+
+        F::from_hash(hash) == self.fingerprints[h0] ^ self.fingerprints[h1] ^ self.fingerprints[h2]
+    }
+
+    /// Number of distinct keys the filter was built from.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn n_fingerprints(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    pub fn bits_per_key(&self) -> f64 {
+        (self.fingerprints.len() as u64 * F::BITS as u64) as f64 / self.len.max(1) as f64
+    }
+
+    /// Segment length and count for `size` keys, following the reference implementation:
+    /// segments of `2^floor(ln(size) / ln(3.33) + 2.25)` slots and
+    /// `max(1.125, 0.875 + 0.25 * ln(10^6) / ln(size))` slots per key.
+    fn dimensions(size: u32) -> (u32, u32) {
+        let segment_length = if size <= 1 {
+            4
+        } else {
+            (1u32 << ((size as f64).ln() / 3.33f64.ln() + 2.25).floor() as u32).min(MAX_SEGMENT_LENGTH)
+        };
+        let size_factor = if size <= 1 { 0.0 } else { 1.125f64.max(0.875 + 0.25 * 1e6f64.ln() / (size as f64).ln()) };
+        let capacity = (size as f64 * size_factor).round() as u32;
+        let segment_count = capacity.div_ceil(segment_length).saturating_sub(2).max(1);
+
+        (segment_length, segment_count)
+    }
+
+    fn mix(&self, key_hash: u64) -> u64 {
+        fingerprint::mix64(key_hash.wrapping_add(self.mix_seed))
+    }
+
+    fn slots(&self, hash: u64) -> [usize; 3] {
+        let mask = self.segment_length - 1;
+        let h0 = ((hash as u128 * self.segment_count_length as u128) >> 64) as u32;
+        let h1 = (h0 + self.segment_length) ^ ((hash >> 18) as u32 & mask);
+        let h2 = (h0 + 2 * self.segment_length) ^ (hash as u32 & mask);
+
+        [h0 as usize, h1 as usize, h2 as usize]
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_fuse_filter8() {
+        let keys: Vec<u64> = (0..1000000).collect();
+        let filter = BinaryFuseFilter8::build(&keys).unwrap();
+
+        assert_eq!(filter.len(), 1000000);
+        assert!(filter.bits_per_key() < 9.2, "{}", filter.bits_per_key());
+        assert!(keys.iter().all(|key| filter.contains(key)));
+        let false_positives = (1000000..1100000u64).filter(|n| filter.contains(n)).count();
+        assert!(false_positives < 600, "{}", false_positives);
+    }
+
+    #[test]
+    fn test_binary_fuse_filter16_and_32_with_duplicates() {
+        let keys: Vec<u64> = (0..20000u64).chain(0..20000u64).collect();
+        let filter16 = BinaryFuseFilter16::build_with_seed(9, &keys).unwrap();
+        let filter32 = BinaryFuseFilter32::build_with_seed(9, &keys).unwrap();
+
+        assert_eq!(filter16.len(), 20000);
+        assert!(keys.iter().all(|key| filter16.contains(key) && filter32.contains(key)));
+        assert!((20000..120000u64).filter(|n| filter16.contains(n)).count() < 10);
+        assert!((20000..120000u64).all(|n| !filter32.contains(n)));
+    }
+
+    #[test]
+    fn test_binary_fuse_filter_small_sets() {
+        for size in 0..64u64 {
+            let filter = BinaryFuseFilter8::build(0..size).unwrap();
+            assert!((0..size).all(|key| filter.contains(key)));
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use crate::fingerprint;
+use crate::hash;
+use crate::params::BloomParams;
+#[cfg(feature = "serde")]
+use crate::error::{Error, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+
+/// Bits per block: one 64-byte cache line.
+pub const BLOCK_BITS: usize = 512;
+const MAX_HASHES: u16 = 16;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Default)]
+#[repr(align(64))]
+struct Block([u64; 8]);
+
+/// Blocked Bloom filter (Putze et al., 2007): the first hash selects one cache-line sized
+/// block and all `k` probes of a key fall inside it, so a lookup costs one memory access
+/// instead of `k`.
+///
+/// Keys do not spread evenly over blocks, and the fuller blocks raise the false positive
+/// rate above that of a `BloomFilter` with as many bits. `new` and `with_seed` compensate by
+/// sizing with `false_positive_rate_for`, which accounts for the uneven load.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "BlockedBloomFilterFields"))]
+pub struct BlockedBloomFilter {
+    n_hashes: u16,
+    seed: u64,
+    blocks: Vec<Block>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct BlockedBloomFilterFields {
+    n_hashes: u16,
+    seed: u64,
+    blocks: Vec<Block>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<BlockedBloomFilterFields> for BlockedBloomFilter {
+    type Error = Error;
+
+    fn try_from(fields: BlockedBloomFilterFields) -> Result<Self> {
+        let BlockedBloomFilterFields { n_hashes, seed, blocks } = fields;
+        if n_hashes == 0 || blocks.is_empty() {
+            return Err(Error::Corrupt("a blocked Bloom filter needs at least one block and one hash"));
+        }
+        Ok(BlockedBloomFilter { n_hashes, seed, blocks })
+    }
+}
+
+impl BlockedBloomFilter {
+    pub fn new(false_positive_rate: f64, expected_item_count: u64) -> Self {
+        BlockedBloomFilter::with_seed(rand::random(), false_positive_rate, expected_item_count)
+    }
+
+    pub fn with_seed(seed: u64, false_positive_rate: f64, expected_item_count: u64) -> Self {
+        let (n_bits, n_hashes) = BlockedBloomFilter::dimensions_for(expected_item_count, false_positive_rate);
+        BlockedBloomFilter::from_dimensions(seed, n_bits, n_hashes)
+    }
+
+    /// `n_bits` is rounded up to a whole number of blocks.
+    pub fn with_dimensions(n_bits: usize, n_hashes: u16) -> Self {
+        BlockedBloomFilter::from_dimensions(rand::random(), n_bits, n_hashes)
+    }
+
+    fn from_dimensions(seed: u64, n_bits: usize, n_hashes: u16) -> Self {
+        assert!(n_bits > 0 && n_hashes > 0, "a Bloom filter needs at least one bit and one hash");
+        let n_blocks = n_bits.div_ceil(BLOCK_BITS);
+
+        BlockedBloomFilter { n_hashes, seed, blocks: vec![Block::default(); n_blocks] }
+    }
+
+    pub fn put<T: Hash>(&mut self, value: T) {
+        let (block, h2) = self.locate(&value);
+        let words = &mut self.blocks[block].0;
+        for bit in Self::block_bits(h2, self.n_hashes) {
+            words[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    pub fn contains<T: Hash>(&self, value: T) -> bool {
+        let (block, h2) = self.locate(&value);
+        let words = &self.blocks[block].0;
+        Self::block_bits(h2, self.n_hashes).all(|bit| words[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    pub fn n_hashes(&self) -> u16 {
+        self.n_hashes
+    }
+
+    pub fn n_bits(&self) -> usize {
+        self.blocks.len() * BLOCK_BITS
+    }
+
+    pub fn n_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Expected false positive rate once `item_count` distinct items have been put.
+    pub fn false_positive_rate_at(&self, item_count: u64) -> f64 {
+        Self::false_positive_rate_for(self.n_bits(), self.n_hashes, item_count)
+    }
+
+    /// Fraction of bits that are set.
+    pub fn fill_ratio(&self) -> f64 {
+        let set: u32 = self.blocks.iter().flat_map(|block| block.0.iter()).map(|word| word.count_ones()).sum();
+        set as f64 / self.n_bits() as f64
+    }
+
+    /// False positive rate of a blocked filter with `n_bits` and `n_hashes` holding `items`.
+    /// A block receives `i` keys with Poisson probability of mean `items * BLOCK_BITS / n_bits`,
+    /// and then answers like a `BLOCK_BITS` Bloom filter with `i` items:
+    /// `sum_i Poisson(i) * (1 - (1 - 1/B)^(k * i))^k`. Only the terms within ten standard
+    /// deviations of the mean are summed; the rest are far below `f64` precision.
+    pub fn false_positive_rate_for(n_bits: usize, n_hashes: u16, items: u64) -> f64 {
+        let n_blocks = n_bits.div_ceil(BLOCK_BITS).max(1);
+        let mean = items as f64 / n_blocks as f64;
+        if mean == 0.0 {
+            return 0.0;
+        }
+
+        let k = n_hashes as f64;
+        let bit_unset = 1.0 - 1.0 / BLOCK_BITS as f64;
+        let spread = 10.0 * mean.sqrt() + 10.0;
+        let first = (mean - spread).max(0.0).floor() as u64;
+        let last = (mean + spread).ceil() as u64;
+        let mut log_poisson = first as f64 * mean.ln() - mean - ln_factorial(first);
+        let mut rate = 0.0;
+        for i in first..=last {
+            if i > first {
+                log_poisson += mean.ln() - (i as f64).ln();
+            }
+            rate += log_poisson.exp() * (1.0 - bit_unset.powf(k * i as f64)).powf(k);
+        }
+        rate.min(1.0)
+    }
+
+    /// Fewest bits, and the hash count achieving it, for which a blocked filter holding
+    /// `expected_items` stays at or below `false_positive_rate`. Blocking moves the best hash
+    /// count little, so only counts within two of the unblocked optimum are tried. Panics when
+    /// no filter addressable in a `usize` of bits gets that low.
+    pub fn dimensions_for(expected_items: u64, false_positive_rate: f64) -> (usize, u16) {
+        assert!(false_positive_rate > 0.0 && false_positive_rate < 1.0, "false positive rate must be in (0, 1)");
+
+        let unblocked = BloomParams::for_items_and_fpr(expected_items, false_positive_rate);
+        let n_hashes = unblocked.n_hashes().clamp(1, MAX_HASHES);
+        let start = unblocked.n_bits() / BLOCK_BITS;
+        (n_hashes.saturating_sub(2).max(1)..=(n_hashes + 2).min(MAX_HASHES))
+            .filter_map(|n_hashes| Self::blocks_for(expected_items, false_positive_rate, n_hashes, start)
+                .map(|n_blocks| (n_blocks * BLOCK_BITS, n_hashes)))
+            .min()
+            .expect("blocked Bloom filter would be too large for the item count and false positive rate")
+    }
+
+    /// Fewest blocks reaching `false_positive_rate` with `n_hashes`, if any number of blocks
+    /// does: a galloping search from `start` brackets the answer, then a binary search
+    /// narrows it down.
+    fn blocks_for(expected_items: u64, false_positive_rate: f64, n_hashes: u16, start: usize) -> Option<usize> {
+        let rate = |n_blocks: usize| Self::false_positive_rate_for(n_blocks * BLOCK_BITS, n_hashes, expected_items);
+        let mut high = start.max(1);
+        let mut low = high / 2;
+        if rate(high) > false_positive_rate {
+            while rate(high) > false_positive_rate {
+                if high > usize::MAX / 4 / BLOCK_BITS {
+                    return None;
+                }
+                low = high;
+                high *= 2;
+            }
+        } else {
+            while low > 0 && rate(low) <= false_positive_rate {
+                high = low;
+                low /= 2;
+            }
+        }
+
+        while low + 1 < high {
+            let middle = low + (high - low) / 2;
+            if rate(middle) > false_positive_rate { low = middle } else { high = middle }
+        }
+        Some(high)
+    }
+
+    fn locate<T: Hash>(&self, value: &T) -> (usize, u64) {
+        let (h1, h2) = hash::hash128(value, self.seed);
+        (((h1 as u128 * self.blocks.len() as u128) >> 64) as usize, h2)
+    }
+
+    /// Independent 9-bit fields of `h2`, extended by remixing every seven probes. Double
+    /// hashing would correlate the probes of different keys within so small a block and
+    /// push the false positive rate measurably above `false_positive_rate_for`.
+    fn block_bits(h2: u64, n_hashes: u16) -> impl Iterator<Item = usize> {
+        const FIELDS_PER_WORD: u16 = 64 / 9;
+        (0..n_hashes).scan(h2, |bits, i| {
+            if i > 0 && i % FIELDS_PER_WORD == 0 {
+                *bits = fingerprint::mix64(*bits);
+            }
+            let bit = (*bits >> (9 * (i % FIELDS_PER_WORD))) as usize % BLOCK_BITS;
+            Some(bit)
+        })
+    }
+}
+
+/// `ln(n!)`, summed exactly for small `n` and by Stirling's series beyond.
+fn ln_factorial(n: u64) -> f64 {
+    if n < 32 {
+        return (2..=n).map(|i| (i as f64).ln()).sum();
+    }
+    let n = n as f64;
+    n * n.ln() - n + 0.5 * (2.0 * std::f64::consts::PI * n).ln() + 1.0 / (12.0 * n) - 1.0 / (360.0 * n.powi(3))
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocked_bloom_filter_accuracy() {
+        let mut filter = BlockedBloomFilter::with_seed(1, 0.01, 100000);
+        (0..100000u64).for_each(|n| filter.put(n));
+
+        assert!((0..100000u64).all(|n| filter.contains(n)));
+        // Sized to exactly 1%: expect 2000 of 200000, sigma 45.
+        let false_positives = (100000..300000u64).filter(|n| filter.contains(n)).count();
+        assert!(false_positives < 2200, "{}", false_positives);
+        assert!(filter.false_positive_rate_at(100000) <= 0.01);
+    }
+
+    #[test]
+    fn test_blocked_bloom_filter_parameters() {
+        let (n_bits, n_hashes) = BlockedBloomFilter::dimensions_for(100000, 0.01);
+        let unblocked = BloomParams::for_items_and_fpr(100000, 0.01).n_bits();
+
+        assert_eq!(n_bits % BLOCK_BITS, 0);
+        assert!(n_bits > unblocked && n_bits < unblocked * 13 / 10, "{} {}", n_bits, unblocked);
+        assert!((5..=8).contains(&n_hashes), "{}", n_hashes);
+
+        // Same bits and hashes: blocking costs accuracy.
+        let rate = BlockedBloomFilter::false_positive_rate_for(unblocked, 7, 100000);
+        assert!(rate > 0.01 && rate < 0.015, "{}", rate);
+        assert_eq!(BlockedBloomFilter::false_positive_rate_for(n_bits, n_hashes, 0), 0.0);
+    }
+
+    #[test]
+    fn test_blocked_bloom_filter_parameters_for_large_sets() {
+        for &(items, rate) in [(10_000_000u64, 0.01), (100_000_000, 0.001)].iter() {
+            let (n_bits, n_hashes) = BlockedBloomFilter::dimensions_for(items, rate);
+            let unblocked = BloomParams::for_items_and_fpr(items, rate).n_bits();
+
+            assert!(n_bits > unblocked && n_bits < unblocked * 13 / 10, "{} {}", n_bits, unblocked);
+            assert!(BlockedBloomFilter::false_positive_rate_for(n_bits, n_hashes, items) <= rate);
+            assert!(BlockedBloomFilter::false_positive_rate_for(n_bits - BLOCK_BITS, n_hashes, items) > rate);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "too large")]
+    fn test_blocked_bloom_filter_parameters_beyond_usize() {
+        BlockedBloomFilter::dimensions_for(u64::MAX / 2, 0.01);
+    }
+
+    #[test]
+    fn test_ln_factorial() {
+        assert_eq!(ln_factorial(0), 0.0);
+        assert!((ln_factorial(5) - 120f64.ln()).abs() < 1e-12);
+        let exact: f64 = (2..=100).map(|i| (i as f64).ln()).sum();
+        assert!((ln_factorial(100) - exact).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_blocked_bloom_filter_with_dimensions() {
+        let mut filter = BlockedBloomFilter::with_dimensions(1000, 4);
+        assert_eq!(filter.n_blocks(), 2);
+        assert_eq!(filter.n_bits(), 1024);
+
+        filter.put("a");
+        assert!(filter.contains("a"));
+        assert!(filter.fill_ratio() > 0.0 && filter.fill_ratio() <= 4.0 / 1024.0);
+    }
+}
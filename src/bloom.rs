@@ -1,34 +1,116 @@
+use crate::error::{Error, Result};
+use crate::format::{self, Header};
+use crate::hash;
+use crate::params::BloomParams;
 use bit_set::BitSet;
-use std::collections::hash_map::{RandomState, DefaultHasher};
-use std::hash::{BuildHasher, Hasher, Hash};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::hash::Hash;
+use std::io::{Read, Write};
+use std::ops::{BitAndAssign, BitOrAssign};
 
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "BloomFilterFields"))]
 pub struct BloomFilter {
     n_hashes: u16,
     n_bits: usize,
+    seed: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::bit_set"))]
     bit_set: BitSet,
-    states: Vec<RandomState>
+}
+
+/// `BloomFilter` as deserialized, before `TryFrom` checks its invariants.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct BloomFilterFields {
+    n_hashes: u16,
+    n_bits: usize,
+    seed: u64,
+    #[serde(with = "crate::serde_support::bit_set")]
+    bit_set: BitSet,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<BloomFilterFields> for BloomFilter {
+    type Error = Error;
+
+    fn try_from(fields: BloomFilterFields) -> Result<Self> {
+        let BloomFilterFields { n_hashes, n_bits, seed, bit_set } = fields;
+        if n_bits == 0 || n_hashes == 0 {
+            return Err(Error::Corrupt("a Bloom filter needs at least one bit and one hash"));
+        }
+        Ok(BloomFilter { n_hashes, n_bits, seed, bit_set })
+    }
 }
 
 impl BloomFilter {
+    /// Smallest filter holding `expected_item_count` items at `false_positive_rate`. The
+    /// smallest filter of all, for an `expected_item_count` of 0, is a single bit, which
+    /// reports every item once anything has been put.
     pub fn new(false_positive_rate: f64, expected_item_count: u64) -> Self {
-        let n_hashes = BloomFilter::get_hash_count(false_positive_rate);
-        let n_bits = BloomFilter::get_bit_count(n_hashes, expected_item_count);
+        BloomFilter::with_seed(rand::random(), false_positive_rate, expected_item_count)
+    }
+
+    /// Creates a filter whose probe positions are fully determined by `seed`, so filters
+    /// built with the same seed and parameters agree on every bit in any process that fills
+    /// them through `put_bytes`.
+    pub fn with_seed(seed: u64, false_positive_rate: f64, expected_item_count: u64) -> Self {
+        BloomFilter::with_params(seed, &BloomParams::for_items_and_fpr(expected_item_count, false_positive_rate))
+    }
+
+    /// Creates the best filter for `expected_item_count` that fits in `bytes` of bits;
+    /// `false_positive_rate_at(expected_item_count)` reports the rate it will reach. Panics
+    /// if `bytes` is 0.
+    pub fn with_memory(bytes: usize, expected_item_count: u64) -> Self {
+        assert!(bytes > 0, "a Bloom filter needs at least one byte");
+        BloomFilter::with_params(rand::random(), &BloomParams::for_items_and_bits(expected_item_count, bytes * 8))
+    }
+
+    pub fn with_dimensions(n_bits: usize, n_hashes: u16) -> Self {
+        BloomFilter::from_dimensions(rand::random(), n_bits, n_hashes)
+    }
+
+    pub fn with_params(seed: u64, params: &BloomParams) -> Self {
+        BloomFilter::from_dimensions(seed, params.n_bits(), params.n_hashes())
+    }
+
+    fn from_dimensions(seed: u64, n_bits: usize, n_hashes: u16) -> Self {
+        assert!(n_bits > 0 && n_hashes > 0, "a Bloom filter needs at least one bit and one hash");
         let bit_set = BitSet::with_capacity(n_bits);
-        let states: Vec<RandomState> = (0..n_hashes).map(|_x| RandomState::new()).collect();
 
-        BloomFilter { n_hashes, n_bits, bit_set, states }
+        BloomFilter { n_hashes, n_bits, seed, bit_set }
     }
 
+    /// Puts `value` as hashed by its `Hash` impl, which can feed different bytes on another
+    /// target or toolchain. Filters that are persisted or shared with other processes should
+    /// be filled with `put_bytes` instead.
     pub fn put<T: Hash>(&mut self, value: T) {
         let bit_set = &mut self.bit_set;
 
-        Self::get_bits(&self.states, &value, &self.n_bits).
+        Self::get_bits(self.seed, self.n_hashes, self.n_bits, &value).
             for_each(|bit| { bit_set.insert(bit); })
     }
 
     pub fn contains<T: Hash>(&self, value: T) -> bool {
-        Self::get_bits(&self.states, &value, &self.n_bits)
+        Self::get_bits(self.seed, self.n_hashes, self.n_bits, &value)
+            .all(|bit| self.bit_set.contains(bit))
+    }
+
+    /// Puts the key whose encoding is exactly `bytes`, e.g. a UTF-8 string or an integer's
+    /// `to_le_bytes()`. The probe positions are `hash::probes` over `hash::hash128_bytes(bytes,
+    /// seed)`, the same on every platform.
+    pub fn put_bytes(&mut self, bytes: &[u8]) {
+        let bit_set = &mut self.bit_set;
+
+        Self::get_bits_for_bytes(self.seed, self.n_hashes, self.n_bits, bytes).
+            for_each(|bit| { bit_set.insert(bit); })
+    }
+
+    pub fn contains_bytes(&self, bytes: &[u8]) -> bool {
+        Self::get_bits_for_bytes(self.seed, self.n_hashes, self.n_bits, bytes)
             .all(|bit| self.bit_set.contains(bit))
     }
 
@@ -40,24 +122,130 @@ impl BloomFilter {
         self.n_bits
     }
 
-    fn get_hash_count(false_positive_rate: f64) -> u16 {
-        -false_positive_rate.log2().ceil() as u16
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Expected false positive rate once `item_count` distinct items have been put.
+    pub fn false_positive_rate_at(&self, item_count: u64) -> f64 {
+        BloomParams::false_positive_rate_for(self.n_bits, self.n_hashes, item_count)
     }
 
-    fn get_bit_count(n_hashes: u16, expected_item_count: u64) -> usize {
-        ((expected_item_count as f64) * (14.4 as f64) * (n_hashes as f64)) as usize
+    /// Fraction of bits that are set.
+    pub fn fill_ratio(&self) -> f64 {
+        self.bit_set.len() as f64 / self.n_bits as f64
     }
 
-    fn hash<T: Hash>(value: &T, mut hasher: DefaultHasher) -> u64 {
-        value.hash(&mut hasher);
-        hasher.finish()
+    /// Swamidass-Baldi estimate of the number of distinct items put into the filter:
+    /// `-(n_bits / n_hashes) * ln(1 - set_bits / n_bits)`. Infinite once every bit is set.
+    pub fn estimated_len(&self) -> f64 {
+        -(self.n_bits as f64 / self.n_hashes as f64) * (1.0 - self.fill_ratio()).ln()
     }
 
-    fn get_bits<'b, T:'b + Hash>(states: &'b Vec<RandomState>, value: &'b T, n_bits: &'b usize)
-                                 -> impl Iterator<Item = usize> + 'b {
-        states.iter().
-            map(move |state| Self::hash(value, state.build_hasher())).
-            map(move |hash_value| (hash_value as usize) % n_bits)
+    /// Probability that `contains` answers `true` for an item never put, given the bits
+    /// set so far.
+    pub fn current_false_positive_rate(&self) -> f64 {
+        self.fill_ratio().powi(self.n_hashes as i32)
+    }
+
+    /// Adds every item of `other` to this filter. Both filters must share `n_bits`,
+    /// `n_hashes` and seed.
+    pub fn union(&mut self, other: &BloomFilter) -> Result<()> {
+        self.check_compatible(other)?;
+        self.bit_set.union_with(&other.bit_set);
+        Ok(())
+    }
+
+    /// Keeps only the bits set in both filters. `contains` still has no false negatives
+    /// for items put into both, but may answer `true` for items put into only one.
+    pub fn intersect(&mut self, other: &BloomFilter) -> Result<()> {
+        self.check_compatible(other)?;
+        self.bit_set.intersect_with(&other.bit_set);
+        Ok(())
+    }
+
+    fn check_compatible(&self, other: &BloomFilter) -> Result<()> {
+        if self.n_bits != other.n_bits {
+            return Err(Error::Incompatible("n_bits differ"));
+        }
+        if self.n_hashes != other.n_hashes {
+            return Err(Error::Incompatible("n_hashes differ"));
+        }
+        if self.seed != other.seed {
+            return Err(Error::Incompatible("seeds differ"));
+        }
+        Ok(())
+    }
+
+    /// Serializes the filter in the versioned, checksummed format described in `format`,
+    /// whose hash scheme only covers keys put with `put_bytes`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = self.header();
+        let mut payload = self.bit_set.get_ref().to_bytes();
+        payload.resize(header.payload_len(), 0);
+
+        let mut bytes = Vec::with_capacity(format::HEADER_LEN + payload.len() + format::CHECKSUM_LEN);
+        bytes.extend_from_slice(&header.encode());
+        bytes.extend_from_slice(&payload);
+        bytes.extend_from_slice(&format::checksum(&bytes, &[]).to_le_bytes());
+        bytes
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (header, payload) = format::split(bytes)?;
+        let bit_set = BitSet::from_bytes(payload);
+
+        Ok(BloomFilter { n_hashes: header.n_hashes, n_bits: header.n_bits, seed: header.seed, bit_set })
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
+        let mut bytes = vec![0u8; format::HEADER_LEN];
+        reader.read_exact(&mut bytes)?;
+        let header = Header::decode(&bytes)?;
+
+        let remaining = (header.payload_len() + format::CHECKSUM_LEN) as u64;
+        reader.take(remaining).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != format::HEADER_LEN as u64 + remaining {
+            return Err(Error::Corrupt("truncated bit payload"));
+        }
+
+        BloomFilter::from_bytes(&bytes)
+    }
+
+    fn header(&self) -> Header {
+        Header { n_hashes: self.n_hashes, n_bits: self.n_bits, seed: self.seed }
+    }
+
+    pub(crate) fn get_bits<T: Hash>(seed: u64, n_hashes: u16, n_bits: usize, value: &T)
+                                    -> impl Iterator<Item = usize> {
+        let (h1, h2) = hash::hash128(value, seed);
+        hash::probes(h1, h2, n_hashes, n_bits)
+    }
+
+    pub(crate) fn get_bits_for_bytes(seed: u64, n_hashes: u16, n_bits: usize, bytes: &[u8])
+                                     -> impl Iterator<Item = usize> {
+        let (h1, h2) = hash::hash128_bytes(bytes, seed);
+        hash::probes(h1, h2, n_hashes, n_bits)
+    }
+}
+
+
+/// Panics if the filters are incompatible; use `BloomFilter::union` to handle that case.
+impl BitOrAssign<&BloomFilter> for BloomFilter {
+    fn bitor_assign(&mut self, other: &BloomFilter) {
+        self.union(other).expect("cannot union Bloom filters");
+    }
+}
+
+/// Panics if the filters are incompatible; use `BloomFilter::intersect` to handle that case.
+impl BitAndAssign<&BloomFilter> for BloomFilter {
+    fn bitand_assign(&mut self, other: &BloomFilter) {
+        self.intersect(other).expect("cannot intersect Bloom filters");
     }
 }
 
@@ -65,15 +253,16 @@ impl BloomFilter {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::params::MAX_HASHES;
     use rand::distributions::{Distribution, Uniform};
     use std::collections::HashSet;
     use std::iter::FromIterator;
 
     #[test]
     fn test_bloom_filter_parameters() {
-        let mut bloom = BloomFilter::new(0.1, 100);
+        let bloom = BloomFilter::new(0.1, 100);
         assert_eq!(bloom.n_hashes(), 3);
-        assert_eq!(bloom.n_bits(), 4320);
+        assert_eq!(bloom.n_bits(), 481);
     }
 
     #[test]
@@ -93,6 +282,190 @@ pub mod tests {
         let false_inputs: Vec<u64> = (0..n_inputs).map(|_n| between.sample(&mut rng)).filter(|n| !inputs.contains(n)).collect();
         let false_error_rate: f64 = (false_inputs.iter().map(|n| bloom.contains(n) as u16).sum::<u16>() as f64) / false_inputs.len() as f64;
 
-        assert!(false_error_rate < 0.1_f64, false_error_rate);
+        // The filter is sized for exactly 10%, so allow for sampling noise around it.
+        assert!(false_error_rate < 0.105_f64, "{}", false_error_rate);
+    }
+
+    #[test]
+    fn test_bloom_filter_seeded_filters_agree() {
+        let mut first = BloomFilter::with_seed(7, 0.01, 1000);
+        let mut second = BloomFilter::with_seed(7, 0.01, 1000);
+        let mut other = BloomFilter::with_seed(8, 0.01, 1000);
+        (0..1000u64).for_each(|n| { first.put(n); second.put(n); other.put(n); });
+
+        assert_eq!(first.seed(), 7);
+        assert_eq!(first.bit_set, second.bit_set);
+        assert_ne!(first.bit_set, other.bit_set);
+    }
+
+    #[test]
+    fn test_bloom_filter_byte_keys_have_pinned_bits() {
+        let mut bloom = BloomFilter::from_dimensions(42, 1000, 4);
+        bloom.put_bytes(b"filters");
+        assert_eq!(bloom.bit_set.iter().collect::<Vec<_>>(), vec![225, 364, 504, 646]);
+        assert!(bloom.contains_bytes(b"filters"));
+        assert!(!bloom.contains_bytes(b"filter"));
+
+        let mut bloom = BloomFilter::from_dimensions(0, 64, 3);
+        bloom.put_bytes(&7u64.to_le_bytes());
+        assert_eq!(bloom.bit_set.iter().collect::<Vec<_>>(), vec![13, 15, 18]);
+    }
+
+    #[test]
+    fn test_bloom_filter_bytes_round_trip() {
+        let mut bloom = BloomFilter::new(0.01, 1000);
+        (0..1000u64).for_each(|n| bloom.put(n));
+
+        let mut bytes = Vec::new();
+        bloom.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes, bloom.to_bytes());
+
+        let restored = BloomFilter::read_from(bytes.as_slice()).unwrap();
+        assert_eq!(restored.n_hashes(), bloom.n_hashes());
+        assert_eq!(restored.n_bits(), bloom.n_bits());
+        assert_eq!(restored.seed(), bloom.seed());
+        assert!((0..1000u64).all(|n| restored.contains(n)));
+        assert_eq!(restored.to_bytes(), bytes);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_bloom_filter_reads_hash_scheme_1() {
+        // Written by hand from the format description: seed 42, 1000 bits, 4 hashes and the
+        // probes of the key b"filters", 225, 364, 504 and 646, set MSB first.
+        let mut bytes = b"FLTR\x01\x00\x01\x00\x04\x00\x00\x00".to_vec();
+        bytes.extend_from_slice(&42u64.to_le_bytes());
+        bytes.extend_from_slice(&1000u64.to_le_bytes());
+        let mut payload = [0u8; 125];
+        [225, 364, 504, 646].iter().for_each(|&bit: &usize| payload[bit / 8] |= 0x80 >> (bit % 8));
+        bytes.extend_from_slice(&payload);
+        bytes.extend_from_slice(&format::checksum(&bytes, &[]).to_le_bytes());
+
+        let bloom = BloomFilter::from_bytes(&bytes).unwrap();
+        assert!(bloom.contains_bytes(b"filters"));
+        let mut written = BloomFilter::from_dimensions(42, 1000, 4);
+        written.put_bytes(b"filters");
+        assert_eq!(written.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_bloom_filter_rejects_bad_bytes() {
+        let mut bloom = BloomFilter::new(0.1, 10);
+        bloom.put("a");
+        let bytes = bloom.to_bytes();
+
+        let mut corrupted = bytes.clone();
+        corrupted[format::HEADER_LEN] ^= 0x01;
+        assert!(matches!(BloomFilter::from_bytes(&corrupted), Err(Error::ChecksumMismatch { .. })));
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert!(matches!(BloomFilter::from_bytes(&wrong_magic), Err(Error::BadMagic)));
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 9;
+        assert!(matches!(BloomFilter::from_bytes(&wrong_version), Err(Error::UnsupportedVersion(9))));
+
+        let mut wrong_scheme = bytes.clone();
+        wrong_scheme[6] = 9;
+        assert!(matches!(BloomFilter::from_bytes(&wrong_scheme), Err(Error::UnsupportedHashScheme(9))));
+
+        assert!(matches!(BloomFilter::read_from(&bytes[..bytes.len() - 1]), Err(Error::Corrupt(_))));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_bloom_filter_serde_round_trip() {
+        let mut bloom = BloomFilter::new(0.01, 1000);
+        (0..1000u64).for_each(|n| bloom.put(n));
+
+        let json = serde_json::to_string(&bloom).unwrap();
+        let restored: BloomFilter = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored.to_bytes(), bloom.to_bytes());
+        assert!((0..10000u64).all(|n| restored.contains(n) == bloom.contains(n)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_bloom_filter_serde_rejects_bad_dimensions() {
+        let no_bits = r#"{"n_hashes":3,"n_bits":0,"seed":1,"bit_set":[]}"#;
+        assert!(serde_json::from_str::<BloomFilter>(no_bits).is_err());
+        let no_hashes = r#"{"n_hashes":0,"n_bits":64,"seed":1,"bit_set":[]}"#;
+        assert!(serde_json::from_str::<BloomFilter>(no_hashes).is_err());
+        let valid = r#"{"n_hashes":3,"n_bits":64,"seed":1,"bit_set":[]}"#;
+        assert!(!serde_json::from_str::<BloomFilter>(valid).unwrap().contains(1u64));
+    }
+
+    #[test]
+    fn test_bloom_filter_union_and_intersect() {
+        let mut evens = BloomFilter::with_seed(1, 0.01, 1000);
+        let mut odds = BloomFilter::with_seed(1, 0.01, 1000);
+        (0..1000u64).for_each(|n| if n % 2 == 0 { evens.put(n) } else { odds.put(n) });
+
+        let mut both = BloomFilter::with_seed(1, 0.01, 1000);
+        both |= &evens;
+        both |= &odds;
+        assert!((0..1000u64).all(|n| both.contains(n)));
+
+        let mut common = BloomFilter::with_seed(1, 0.01, 1000);
+        common.put(5000u64);
+        common.put(42u64);
+        both.put(5000u64);
+        common &= &both;
+        assert!(common.contains(5000u64));
+        assert!(common.contains(42u64));
+        assert!(!common.contains(7777u64) || both.contains(7777u64));
+    }
+
+    #[test]
+    fn test_bloom_filter_union_rejects_incompatible() {
+        let mut bloom = BloomFilter::with_seed(1, 0.01, 1000);
+
+        assert!(matches!(bloom.union(&BloomFilter::with_seed(2, 0.01, 1000)), Err(Error::Incompatible(_))));
+        assert!(matches!(bloom.union(&BloomFilter::with_seed(1, 0.01, 2000)), Err(Error::Incompatible(_))));
+        assert!(matches!(bloom.intersect(&BloomFilter::with_seed(1, 0.001, 1000)), Err(Error::Incompatible(_))));
+    }
+
+    #[test]
+    fn test_bloom_filter_estimates() {
+        let mut bloom = BloomFilter::new(0.01, 10000);
+        assert_eq!(bloom.fill_ratio(), 0.0);
+        assert_eq!(bloom.estimated_len(), 0.0);
+        assert_eq!(bloom.current_false_positive_rate(), 0.0);
+
+        (0..5000u64).for_each(|n| bloom.put(n));
+        let estimate = bloom.estimated_len();
+        assert!((estimate - 5000.0).abs() < 250.0, "{}", estimate);
+        assert!(bloom.fill_ratio() > 0.0 && bloom.fill_ratio() < 1.0);
+        assert!(bloom.current_false_positive_rate() < 0.01);
+    }
+
+    #[test]
+    fn test_bloom_filter_with_memory_and_dimensions() {
+        let bloom = BloomFilter::with_memory(1200, 1000);
+        assert_eq!(bloom.n_bits(), 9600);
+        assert_eq!(bloom.n_hashes(), 7);
+        assert!(bloom.false_positive_rate_at(1000) < 0.01);
+        assert!(bloom.false_positive_rate_at(2000) > 0.01);
+        assert_eq!(BloomFilter::with_memory(1200, 0).n_hashes(), MAX_HASHES);
+        assert_eq!(BloomFilter::with_memory(1, 1000).n_bits(), 8);
+
+        let mut smallest = BloomFilter::new(0.01, 0);
+        assert_eq!((smallest.n_bits(), smallest.n_hashes()), (1, 7));
+        assert!(!smallest.contains("key"));
+        smallest.put("other");
+        assert!(smallest.contains("key"));
+
+        let mut bloom = BloomFilter::with_dimensions(1024, 4);
+        assert_eq!(bloom.n_bits(), 1024);
+        assert_eq!(bloom.n_hashes(), 4);
+        bloom.put("key");
+        assert!(bloom.contains("key"));
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one byte")]
+    fn test_bloom_filter_with_no_memory() {
+        BloomFilter::with_memory(0, 1000);
+    }
+}
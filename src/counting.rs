@@ -0,0 +1,225 @@
+use crate::bloom::BloomFilter;
+use crate::error::{Error, Result};
+use crate::params::BloomParams;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterWidth {
+    Four,
+    Eight,
+    Sixteen,
+}
+
+impl CounterWidth {
+    pub fn bits(self) -> u32 {
+        match self {
+            CounterWidth::Four => 4,
+            CounterWidth::Eight => 8,
+            CounterWidth::Sixteen => 16,
+        }
+    }
+
+    pub fn max_count(self) -> u16 {
+        ((1u32 << self.bits()) - 1) as u16
+    }
+}
+
+/// A Bloom filter with a small counter per slot instead of a bit, so items can be removed.
+///
+/// A counter that reaches its maximum sticks there: further puts and removes leave it
+/// untouched, since its true count is no longer known. This can only cause false
+/// positives, never false negatives.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "CountingBloomFilterFields"))]
+pub struct CountingBloomFilter {
+    n_hashes: u16,
+    n_counters: usize,
+    seed: u64,
+    width: CounterWidth,
+    words: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct CountingBloomFilterFields {
+    n_hashes: u16,
+    n_counters: usize,
+    seed: u64,
+    width: CounterWidth,
+    words: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<CountingBloomFilterFields> for CountingBloomFilter {
+    type Error = Error;
+
+    fn try_from(fields: CountingBloomFilterFields) -> Result<Self> {
+        let CountingBloomFilterFields { n_hashes, n_counters, seed, width, words } = fields;
+        if n_counters == 0 || n_hashes == 0 {
+            return Err(Error::Corrupt("a counting Bloom filter needs at least one counter and one hash"));
+        }
+        if words.len() != n_counters.div_ceil((64 / width.bits()) as usize) {
+            return Err(Error::Corrupt("counter words do not match the counter count"));
+        }
+        Ok(CountingBloomFilter { n_hashes, n_counters, seed, width, words })
+    }
+}
+
+impl CountingBloomFilter {
+    pub fn new(false_positive_rate: f64, expected_item_count: u64, width: CounterWidth) -> Self {
+        CountingBloomFilter::with_seed(rand::random(), false_positive_rate, expected_item_count, width)
+    }
+
+    pub fn with_seed(seed: u64, false_positive_rate: f64, expected_item_count: u64, width: CounterWidth) -> Self {
+        let params = BloomParams::for_items_and_fpr(expected_item_count, false_positive_rate);
+        let n_counters = params.n_bits();
+        let per_word = (64 / width.bits()) as usize;
+        let words = vec![0; n_counters.div_ceil(per_word)];
+
+        CountingBloomFilter { n_hashes: params.n_hashes(), n_counters, seed, width, words }
+    }
+
+    pub fn put<T: Hash>(&mut self, value: T) {
+        let max_count = self.width.max_count();
+
+        for slot in self.slots(&value) {
+            let count = self.get(slot);
+            if count < max_count {
+                self.set(slot, count + 1);
+            }
+        }
+    }
+
+    /// Removes one occurrence of `value`. Fails without modifying the filter if `value`
+    /// cannot have been put, i.e. one of its counters is already zero.
+    pub fn remove<T: Hash>(&mut self, value: T) -> Result<()> {
+        let mut slots: Vec<usize> = self.slots(&value).collect();
+        slots.sort_unstable();
+
+        let mut start = 0;
+        while start < slots.len() {
+            let end = start + slots[start..].iter().take_while(|&&slot| slot == slots[start]).count();
+            if (self.get(slots[start]) as usize) < end - start {
+                return Err(Error::NotPresent);
+            }
+            start = end;
+        }
+
+        let max_count = self.width.max_count();
+        for slot in slots {
+            let count = self.get(slot);
+            if count < max_count {
+                self.set(slot, count - 1);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn contains<T: Hash>(&self, value: T) -> bool {
+        self.slots(&value).all(|slot| self.get(slot) > 0)
+    }
+
+    /// Upper bound on how many times `value` has been put: the smallest of its counters.
+    pub fn count_estimate<T: Hash>(&self, value: T) -> u16 {
+        self.slots(&value).map(|slot| self.get(slot)).min().unwrap_or(0)
+    }
+
+    /// Number of counters stuck at their maximum.
+    pub fn saturated_counters(&self) -> usize {
+        let max_count = self.width.max_count();
+        (0..self.n_counters).filter(|&slot| self.get(slot) == max_count).count()
+    }
+
+    pub fn n_hashes(&self) -> u16 {
+        self.n_hashes
+    }
+
+    pub fn n_counters(&self) -> usize {
+        self.n_counters
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn counter_width(&self) -> CounterWidth {
+        self.width
+    }
+
+    fn slots<T: Hash>(&self, value: &T) -> impl Iterator<Item = usize> {
+        BloomFilter::get_bits(self.seed, self.n_hashes, self.n_counters, value)
+    }
+
+    fn position(&self, slot: usize) -> (usize, u32) {
+        let per_word = (64 / self.width.bits()) as usize;
+        (slot / per_word, (slot % per_word) as u32 * self.width.bits())
+    }
+
+    fn get(&self, slot: usize) -> u16 {
+        let (word, shift) = self.position(slot);
+        ((self.words[word] >> shift) & self.width.max_count() as u64) as u16
+    }
+
+    fn set(&mut self, slot: usize, count: u16) {
+        let (word, shift) = self.position(slot);
+        let mask = (self.width.max_count() as u64) << shift;
+        self.words[word] = (self.words[word] & !mask) | ((count as u64) << shift);
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counting_bloom_filter_put_and_remove() {
+        let mut filter = CountingBloomFilter::new(0.01, 1000, CounterWidth::Four);
+        (0..1000u64).for_each(|n| filter.put(n));
+        assert!((0..1000u64).all(|n| filter.contains(n)));
+
+        (0..500u64).for_each(|n| filter.remove(n).unwrap());
+        assert!((500..1000u64).all(|n| filter.contains(n)));
+        let false_positives = (0..500u64).filter(|&n| filter.contains(n)).count();
+        assert!(false_positives < 25, "{}", false_positives);
+    }
+
+    #[test]
+    fn test_counting_bloom_filter_count_estimate() {
+        let mut filter = CountingBloomFilter::new(0.01, 100, CounterWidth::Eight);
+        (0..3).for_each(|_| filter.put("three"));
+        filter.put("one");
+
+        assert_eq!(filter.count_estimate("three"), 3);
+        assert_eq!(filter.count_estimate("one"), 1);
+        assert_eq!(filter.count_estimate("zero"), 0);
+    }
+
+    #[test]
+    fn test_counting_bloom_filter_rejects_removing_absent_item() {
+        let mut filter = CountingBloomFilter::with_seed(1, 0.01, 100, CounterWidth::Sixteen);
+        filter.put("present");
+
+        assert!(matches!(filter.remove("absent"), Err(Error::NotPresent)));
+        assert!(filter.contains("present"));
+        filter.remove("present").unwrap();
+        assert!(matches!(filter.remove("present"), Err(Error::NotPresent)));
+    }
+
+    #[test]
+    fn test_counting_bloom_filter_saturates() {
+        let mut filter = CountingBloomFilter::new(0.01, 100, CounterWidth::Four);
+        (0..20).for_each(|_| filter.put("hot"));
+
+        assert_eq!(filter.count_estimate("hot"), 15);
+        assert!(filter.saturated_counters() > 0);
+        (0..20).for_each(|_| filter.remove("hot").unwrap());
+        assert!(filter.contains("hot"));
+    }
+}
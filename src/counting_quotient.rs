@@ -0,0 +1,289 @@
+use crate::error::{Error, Result};
+use crate::quotient::{QuotientFilter, MAX_REMAINDER_BITS};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+
+/// Counting quotient filter (Pandey et al., 2017): a quotient filter whose runs store each
+/// remainder `x` together with its count, using the run's slots for the counter digits:
+///
+/// * count 1: `x`
+/// * count 2: `x, x`
+/// * count `c >= 3`, `x > 0`: `x, d..., x`, where the digits encode `c - 3` in base
+///   `2^r - 1` using every `r`-bit symbol except `x`, most significant first. The first
+///   digit is smaller than `x` (a zero digit is prepended if needed), which sets counters
+///   apart from the next, larger remainder of the sorted run.
+/// * count `c >= 3`, `x = 0`: `0, 0, 0, d..., 0`, with non-zero digit symbols.
+///
+/// Small counts thus cost nothing extra and large ones grow logarithmically, all in-slot.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "CountingQuotientFilterFields"))]
+pub struct CountingQuotientFilter {
+    table: QuotientFilter,
+    total_count: u64,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct CountingQuotientFilterFields {
+    table: QuotientFilter,
+    total_count: u64,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<CountingQuotientFilterFields> for CountingQuotientFilter {
+    type Error = Error;
+
+    fn try_from(fields: CountingQuotientFilterFields) -> Result<Self> {
+        let CountingQuotientFilterFields { table, total_count } = fields;
+        if table.remainder_bits() < 2 {
+            return Err(Error::Corrupt("counter digits need at least 2 remainder bits"));
+        }
+        Ok(CountingQuotientFilter { table, total_count })
+    }
+}
+
+impl CountingQuotientFilter {
+    /// Sizes the table for `expected_item_count` distinct items at 75% load, with
+    /// `ceil(-log2(p))` remainder bits (at least 2, and clamped like `QuotientFilter::new`).
+    /// Panics if that takes more than 2^32 slots.
+    pub fn new(false_positive_rate: f64, expected_item_count: u64) -> Self {
+        let quotient_bits = (expected_item_count as f64 / 0.75).log2().ceil().max(1.0) as u32;
+        assert!(quotient_bits <= 32, "a quotient filter holds at most 2^32 slots, too few for expected_item_count");
+        let remainder_bits = (-false_positive_rate.log2()).ceil()
+            .clamp(2.0, MAX_REMAINDER_BITS.min(64 - quotient_bits) as f64) as u32;
+        CountingQuotientFilter::with_options(rand::random(), quotient_bits, remainder_bits)
+    }
+
+    pub fn with_options(seed: u64, quotient_bits: u32, remainder_bits: u32) -> Self {
+        assert!(remainder_bits >= 2, "counter digits need at least 2 remainder bits");
+        CountingQuotientFilter { table: QuotientFilter::with_options(seed, quotient_bits, remainder_bits), total_count: 0 }
+    }
+
+    pub fn increment<T: Hash>(&mut self, value: T) -> Result<()> {
+        self.update(&value, |count| Ok(count.saturating_add(1)))?;
+        self.total_count = self.total_count.saturating_add(1);
+        Ok(())
+    }
+
+    /// Decrements `value`'s count, failing with `Error::NotPresent` when it is already zero.
+    pub fn decrement<T: Hash>(&mut self, value: T) -> Result<()> {
+        self.update(&value, |count| count.checked_sub(1).ok_or(Error::NotPresent))?;
+        self.total_count -= 1;
+        Ok(())
+    }
+
+    /// Number of times `value` was incremented minus decremented. May be larger than the
+    /// true count when another item shares `value`'s fingerprint, never smaller.
+    pub fn count<T: Hash>(&self, value: T) -> u64 {
+        let (quotient, remainder) = self.table.split(self.table.fingerprint(&value));
+        if !self.table.is_occupied(quotient) {
+            return 0;
+        }
+
+        decode_run(&self.table.run(quotient), self.table.remainder_bits()).into_iter().find(|&(r, _)| r == remainder).map_or(0, |(_, count)| count)
+    }
+
+    pub fn contains<T: Hash>(&self, value: T) -> bool {
+        self.count(value) > 0
+    }
+
+    /// Sum of all counts.
+    pub fn total_count(&self) -> u64 {
+        self.total_count
+    }
+
+    /// Number of slots holding remainders or counter digits.
+    pub fn used_slots(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn n_slots(&self) -> usize {
+        self.table.n_slots()
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.table.seed()
+    }
+
+    pub fn load_factor(&self) -> f64 {
+        self.table.load_factor()
+    }
+
+    /// Applies `change` to the count of `value`'s fingerprint and re-encodes its run.
+    fn update<T: Hash, C: FnOnce(u64) -> Result<u64>>(&mut self, value: &T, change: C) -> Result<()> {
+        let (quotient, remainder) = self.table.split(self.table.fingerprint(value));
+        let remainder_bits = self.table.remainder_bits();
+
+        self.table.edit_run(quotient, |run| {
+            let mut entries = decode_run(run, remainder_bits);
+            let index = entries.iter().position(|&(r, _)| r >= remainder).unwrap_or(entries.len());
+            if entries.get(index).map(|&(r, _)| r) != Some(remainder) {
+                entries.insert(index, (remainder, 0));
+            }
+            entries[index].1 = change(entries[index].1)?;
+            if entries[index].1 == 0 {
+                entries.remove(index);
+            }
+            *run = encode_run(&entries, remainder_bits);
+            Ok(())
+        })
+    }
+}
+
+fn decode_run(slots: &[u64], remainder_bits: u32) -> Vec<(u64, u64)> {
+    let base = (1u64 << remainder_bits) - 1;
+    let mut entries = vec![];
+    let mut i = 0;
+    while i < slots.len() {
+        let remainder = slots[i];
+        i += 1;
+
+        let counter_starts = if remainder == 0 {
+            if slots.get(i) == Some(&0) && slots.get(i + 1) == Some(&0) {
+                i += 2;
+                true
+            } else {
+                false
+            }
+        } else {
+            slots.get(i).is_some_and(|&next| next < remainder)
+        };
+
+        let count = if counter_starts {
+            let digits_start = i;
+            while slots[i] != remainder {
+                i += 1;
+            }
+            let value = slots[digits_start..i].iter()
+                .fold(0u64, |value, &symbol| value.saturating_mul(base).saturating_add(digit(symbol, remainder)));
+            i += 1;
+            value.saturating_add(3)
+        } else if slots.get(i) == Some(&remainder) {
+            i += 1;
+            2
+        } else {
+            1
+        };
+        entries.push((remainder, count));
+    }
+    entries
+}
+
+fn encode_run(entries: &[(u64, u64)], remainder_bits: u32) -> Vec<u64> {
+    let base = (1u64 << remainder_bits) - 1;
+    let mut slots = vec![];
+    for &(remainder, count) in entries {
+        match count {
+            0 => {}
+            1 => slots.push(remainder),
+            2 => slots.extend_from_slice(&[remainder, remainder]),
+            _ => {
+                let mut digits = vec![];
+                let mut value = count - 3;
+                while value > 0 || (remainder > 0 && digits.is_empty()) {
+                    digits.push(symbol(value % base, remainder));
+                    value /= base;
+                }
+                digits.reverse();
+                if remainder > 0 && digits[0] > remainder {
+                    digits.insert(0, 0);
+                }
+
+                slots.push(remainder);
+                if remainder == 0 {
+                    slots.extend_from_slice(&[0, 0]);
+                }
+                slots.extend(digits);
+                slots.push(remainder);
+            }
+        }
+    }
+    slots
+}
+
+fn symbol(digit: u64, remainder: u64) -> u64 {
+    if remainder == 0 || digit >= remainder { digit + 1 } else { digit }
+}
+
+fn digit(symbol: u64, remainder: u64) -> u64 {
+    if remainder == 0 || symbol > remainder { symbol - 1 } else { symbol }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_counter_encoding_round_trips() {
+        for &remainder_bits in [2u32, 3, 8].iter() {
+            for remainder in 0..(1u64 << remainder_bits) {
+                for &count in [1u64, 2, 3, 4, 5, 7, 100, 12345, u32::MAX as u64].iter() {
+                    let entries = vec![(remainder, count)];
+                    let slots = encode_run(&entries, remainder_bits);
+                    assert!(slots.iter().all(|&slot| slot < 1 << remainder_bits));
+                    assert_eq!(decode_run(&slots, remainder_bits), entries);
+                }
+            }
+        }
+
+        let entries = vec![(0, 5), (1, 2), (2, 1), (3, 300)];
+        assert_eq!(decode_run(&encode_run(&entries, 2), 2), entries);
+    }
+
+    #[test]
+    fn test_counting_quotient_filter_counts() {
+        let mut filter = CountingQuotientFilter::with_options(2, 13, 10);
+        for n in 0..1000u64 {
+            (0..n % 20).for_each(|_| filter.increment(n).unwrap());
+        }
+
+        assert!((0..1000u64).all(|n| filter.count(n) >= n % 20));
+        let exact = (0..1000u64).filter(|&n| filter.count(n) == n % 20).count();
+        assert!(exact > 990, "{}", exact);
+        assert_eq!(filter.total_count(), (0..1000u64).map(|n| n % 20).sum::<u64>());
+        assert!(filter.used_slots() < 4000);
+    }
+
+    #[test]
+    fn test_counting_quotient_filter_decrement() {
+        let mut filter = CountingQuotientFilter::with_options(4, 4, 3);
+        (0..1000).for_each(|_| filter.increment("many").unwrap());
+        filter.increment("once").unwrap();
+        assert_eq!(filter.count("many"), 1000);
+
+        (0..999).for_each(|_| filter.decrement("many").unwrap());
+        assert_eq!(filter.count("many"), 1);
+        filter.decrement("many").unwrap();
+        assert!(!filter.contains("many"));
+        assert!(matches!(filter.decrement("many"), Err(Error::NotPresent)));
+        assert_eq!(filter.count("once"), 1);
+        assert_eq!(filter.used_slots(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "at most 2^32 slots")]
+    fn test_counting_quotient_filter_rejects_more_than_2_32_slots() {
+        CountingQuotientFilter::new(0.01, u64::MAX);
+    }
+
+    #[test]
+    fn test_counting_quotient_filter_growing_run_moves_neighbours() {
+        // Key 1's counter grows from two slots to four and has to push key 7's run along.
+        let mut filter = CountingQuotientFilter::with_options(64, 3, 2);
+        let mut expected: HashMap<u64, u64> = HashMap::new();
+        for &key in [1u64, 7, 5, 1, 1].iter() {
+            filter.increment(key).unwrap();
+            *expected.entry(filter.table.fingerprint(&key)).or_default() += 1;
+        }
+
+        for &key in [1u64, 5, 7].iter() {
+            assert_eq!(filter.count(key), expected[&filter.table.fingerprint(&key)]);
+        }
+    }
+}
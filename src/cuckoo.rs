@@ -0,0 +1,474 @@
+use crate::error::{Error, Result};
+use crate::fingerprint;
+use crate::hash;
+use rand::Rng;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+
+pub const DEFAULT_BUCKET_SIZE: usize = 4;
+pub const DEFAULT_MAX_KICKS: usize = 500;
+const MAX_LOAD_FACTOR: f64 = 0.95;
+const EMPTY: u16 = 0;
+/// Buckets of this many slots are stored sorted, two to a field; see `CuckooFilter`.
+const SORTED_BUCKET_SIZE: usize = 4;
+
+/// Cuckoo filter (Fan et al., 2014). Each item is reduced to a non-zero fingerprint stored
+/// in one of two buckets; the alternate bucket is derived from the current bucket and the
+/// fingerprint alone (partial-key cuckoo hashing), so fingerprints can be relocated without
+/// the original item.
+///
+/// Buckets of four fingerprints are semi-sorted (section 5.2 of the paper), taken to whole
+/// fingerprints: a bucket is only ever searched as a whole, so it is stored as the rank of
+/// its sorted fingerprints among the `C(2^f + 3, 4)` multisets of four, and two buckets share
+/// one field of `ceil(2 * log2(C(2^f + 3, 4)))` bits. That saves about 1.15 bits per slot over
+/// packing, which other bucket sizes do at `fingerprint_bits` each. The bucket count is not
+/// rounded to a power of two: the alternate of bucket `i` is `h(fingerprint) - i (mod
+/// n_buckets)`, which maps the two buckets onto each other for any table size.
+///
+/// `new` picks the shortest fingerprint that keeps the rate at the 95% design load, which
+/// takes less memory than `BloomFilter` at 3%, at 1% and below 1.5%, though not between,
+/// where the fingerprint rounds up to a whole extra bit.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "CuckooFilterFields"))]
+pub struct CuckooFilter {
+    fingerprint_bits: u32,
+    bucket_size: usize,
+    n_buckets: usize,
+    max_kicks: usize,
+    seed: u64,
+    len: usize,
+    words: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct CuckooFilterFields {
+    fingerprint_bits: u32,
+    bucket_size: usize,
+    n_buckets: usize,
+    max_kicks: usize,
+    seed: u64,
+    len: usize,
+    words: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<CuckooFilterFields> for CuckooFilter {
+    type Error = Error;
+
+    fn try_from(fields: CuckooFilterFields) -> Result<Self> {
+        let CuckooFilterFields { fingerprint_bits, bucket_size, n_buckets, max_kicks, seed, len, words } = fields;
+        if !(2..=16).contains(&fingerprint_bits) || bucket_size == 0 || n_buckets == 0 {
+            return Err(Error::Corrupt("cuckoo filter dimensions out of range"));
+        }
+        let table_bits = CuckooFilter::table_bits(fingerprint_bits, bucket_size, n_buckets);
+        if table_bits.map(|bits| bits.div_ceil(64)) != Some(words.len()) {
+            return Err(Error::Corrupt("fingerprint words do not match the table size"));
+        }
+        if len > n_buckets * bucket_size {
+            return Err(Error::Corrupt("more items than slots"));
+        }
+        Ok(CuckooFilter { fingerprint_bits, bucket_size, n_buckets, max_kicks, seed, len, words })
+    }
+}
+
+impl CuckooFilter {
+    /// Sizes a filter with 4-slot buckets and the shortest fingerprint that keeps the rate
+    /// at the design load within `false_positive_rate`: a lookup compares `2 * bucket_size *
+    /// load` stored fingerprints with its own, which matches each with probability
+    /// `1 / (2^f - 1)`, so `f = ceil(log2(2 * bucket_size * load / p + 1))`.
+    pub fn new(false_positive_rate: f64, expected_item_count: u64) -> Self {
+        let comparisons = 2.0 * DEFAULT_BUCKET_SIZE as f64 * MAX_LOAD_FACTOR;
+        let bits = (comparisons / false_positive_rate + 1.0).log2().ceil() as u32;
+        CuckooFilter::with_options(rand::random(), expected_item_count, bits.clamp(2, 16), DEFAULT_BUCKET_SIZE)
+    }
+
+    /// `fingerprint_bits` must be in `2..=16`.
+    pub fn with_options(seed: u64, expected_item_count: u64, fingerprint_bits: u32, bucket_size: usize) -> Self {
+        assert!((2..=16).contains(&fingerprint_bits), "fingerprint bits must be in 2..=16");
+        assert!(bucket_size > 0, "buckets need at least one slot");
+
+        let n_buckets = (expected_item_count as f64 / (bucket_size as f64 * MAX_LOAD_FACTOR)).ceil() as usize;
+        let n_buckets = n_buckets.max(1);
+        let n_words = CuckooFilter::table_bits(fingerprint_bits, bucket_size, n_buckets)
+            .expect("cuckoo table is too large").div_ceil(64);
+
+        CuckooFilter {
+            fingerprint_bits,
+            bucket_size,
+            n_buckets,
+            max_kicks: DEFAULT_MAX_KICKS,
+            seed,
+            len: 0,
+            words: vec![0; n_words],
+        }
+    }
+
+    /// Inserts `value`, relocating up to `max_kicks` fingerprints to make room. On
+    /// `Error::Full` the filter is left exactly as it was.
+    pub fn put<T: Hash>(&mut self, value: T) -> Result<()> {
+        let (bucket, fingerprint) = self.index_and_fingerprint(&value);
+        let alternate = self.alternate(bucket, fingerprint);
+        if self.insert_into(bucket, fingerprint) || self.insert_into(alternate, fingerprint) {
+            self.len += 1;
+            return Ok(());
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut bucket = if rng.gen() { bucket } else { alternate };
+        let mut fingerprint = fingerprint;
+        // The bucket and the fingerprint put there by each kick. Semi-sorted buckets reorder
+        // their slots on every write, so kicks are undone by fingerprint rather than by slot.
+        let mut kicked: Vec<(usize, u16)> = Vec::with_capacity(self.max_kicks);
+
+        for _ in 0..self.max_kicks {
+            let slot = bucket * self.bucket_size + rng.gen_range(0, self.bucket_size);
+            kicked.push((bucket, fingerprint));
+            fingerprint = self.replace(slot, fingerprint);
+
+            bucket = self.alternate(bucket, fingerprint);
+            if self.insert_into(bucket, fingerprint) {
+                self.len += 1;
+                return Ok(());
+            }
+        }
+
+        for (bucket, placed) in kicked.into_iter().rev() {
+            let slot = self.find(bucket, placed).expect("kicked fingerprint is still in its bucket");
+            fingerprint = self.replace(slot, fingerprint);
+        }
+        Err(Error::Full)
+    }
+
+    pub fn contains<T: Hash>(&self, value: T) -> bool {
+        let (bucket, fingerprint) = self.index_and_fingerprint(&value);
+        self.find(bucket, fingerprint).is_some() || self.find(self.alternate(bucket, fingerprint), fingerprint).is_some()
+    }
+
+    /// Removes one copy of `value`'s fingerprint. Only remove items that were put: removing
+    /// a false positive deletes the fingerprint of some other item.
+    pub fn remove<T: Hash>(&mut self, value: T) -> Result<()> {
+        let (bucket, fingerprint) = self.index_and_fingerprint(&value);
+        let alternate = self.alternate(bucket, fingerprint);
+
+        match self.find(bucket, fingerprint).or_else(|| self.find(alternate, fingerprint)) {
+            Some(slot) => {
+                self.replace(slot, EMPTY);
+                self.len -= 1;
+                Ok(())
+            }
+            None => Err(Error::NotPresent),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Total number of fingerprint slots.
+    pub fn capacity(&self) -> usize {
+        self.n_buckets * self.bucket_size
+    }
+
+    /// Size of the fingerprint table in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        self.words.len() * 8
+    }
+
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.capacity() as f64
+    }
+
+    pub fn fingerprint_bits(&self) -> u32 {
+        self.fingerprint_bits
+    }
+
+    pub fn bucket_size(&self) -> usize {
+        self.bucket_size
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn set_max_kicks(&mut self, max_kicks: usize) {
+        self.max_kicks = max_kicks;
+    }
+
+    /// Upper bound on the false positive rate at the current load: `2 * bucket_size * load /
+    /// (2^f - 1)`, as a fingerprint is never the empty slot's 0.
+    pub fn false_positive_rate(&self) -> f64 {
+        2.0 * self.bucket_size as f64 * self.load_factor() / ((1u64 << self.fingerprint_bits) - 1) as f64
+    }
+
+    fn index_and_fingerprint<T: Hash>(&self, value: &T) -> (usize, u16) {
+        let (h1, h2) = hash::hash128(value, self.seed);
+        let fingerprint = (h2 & ((1u64 << self.fingerprint_bits) - 1)) as u16;
+        let fingerprint = if fingerprint == EMPTY { 1 } else { fingerprint };
+        (((h1 as u128 * self.n_buckets as u128) >> 64) as usize, fingerprint)
+    }
+
+    /// `h(fingerprint) - bucket (mod n_buckets)`, an involution, so either bucket leads to
+    /// the other.
+    fn alternate(&self, bucket: usize, fingerprint: u16) -> usize {
+        let offset = ((fingerprint::mix64(fingerprint as u64) as u128 * self.n_buckets as u128) >> 64) as usize;
+        (offset + self.n_buckets - bucket) % self.n_buckets
+    }
+
+    fn table_bits(fingerprint_bits: u32, bucket_size: usize, n_buckets: usize) -> Option<usize> {
+        if bucket_size == SORTED_BUCKET_SIZE {
+            n_buckets.div_ceil(2).checked_mul(Self::pair_bits(fingerprint_bits) as usize)
+        } else {
+            n_buckets.checked_mul(bucket_size)?.checked_mul(fingerprint_bits as usize)
+        }
+    }
+
+    /// Number of sorted buckets: multisets of four fingerprints, empty slots included.
+    fn n_sorted_buckets(fingerprint_bits: u32) -> u128 {
+        binomial((1u128 << fingerprint_bits) + 3, 4)
+    }
+
+    /// Bits of the field holding two sorted buckets' ranks, at most 119.
+    fn pair_bits(fingerprint_bits: u32) -> u32 {
+        let n = Self::n_sorted_buckets(fingerprint_bits);
+        128 - (n * n - 1).leading_zeros()
+    }
+
+    /// The slot of `bucket` holding `fingerprint`, if any.
+    fn find(&self, bucket: usize, fingerprint: u16) -> Option<usize> {
+        if self.bucket_size == SORTED_BUCKET_SIZE {
+            let position = self.read_bucket(bucket).iter().position(|&stored| stored == fingerprint);
+            return position.map(|position| bucket * SORTED_BUCKET_SIZE + position);
+        }
+        (bucket * self.bucket_size..(bucket + 1) * self.bucket_size).find(|&slot| self.get(slot) == fingerprint)
+    }
+
+    fn insert_into(&mut self, bucket: usize, fingerprint: u16) -> bool {
+        match self.find(bucket, EMPTY) {
+            Some(slot) => { self.replace(slot, fingerprint); true }
+            None => false,
+        }
+    }
+
+    fn get(&self, slot: usize) -> u16 {
+        if self.bucket_size == SORTED_BUCKET_SIZE {
+            return self.read_bucket(slot / SORTED_BUCKET_SIZE)[slot % SORTED_BUCKET_SIZE];
+        }
+        self.read_bits(slot * self.fingerprint_bits as usize, self.fingerprint_bits) as u16
+    }
+
+    /// Stores `fingerprint` in `slot` and returns the fingerprint it held before. In a
+    /// sorted bucket this can move the bucket's other fingerprints to other slots.
+    fn replace(&mut self, slot: usize, fingerprint: u16) -> u16 {
+        if self.bucket_size == SORTED_BUCKET_SIZE {
+            let bucket = slot / SORTED_BUCKET_SIZE;
+            let mut fingerprints = self.read_bucket(bucket);
+            let previous = std::mem::replace(&mut fingerprints[slot % SORTED_BUCKET_SIZE], fingerprint);
+            self.write_bucket(bucket, fingerprints);
+            return previous;
+        }
+        let previous = self.get(slot);
+        self.write_bits(slot * self.fingerprint_bits as usize, self.fingerprint_bits, fingerprint as u128);
+        previous
+    }
+
+    /// A sorted bucket's fingerprints in increasing order. Bucket `2i + 1`'s rank is the
+    /// quotient and bucket `2i`'s the remainder of field `i` divided by `n_sorted_buckets`.
+    fn read_bucket(&self, bucket: usize) -> [u16; SORTED_BUCKET_SIZE] {
+        let n_sorted = Self::n_sorted_buckets(self.fingerprint_bits);
+        let pair_bits = Self::pair_bits(self.fingerprint_bits);
+        let pair = self.read_bits(bucket / 2 * pair_bits as usize, pair_bits);
+        let rank = if bucket % 2 == 0 { pair % n_sorted } else { pair / n_sorted };
+        unrank_multiset(rank, self.fingerprint_bits)
+    }
+
+    fn write_bucket(&mut self, bucket: usize, mut fingerprints: [u16; SORTED_BUCKET_SIZE]) {
+        fingerprints.sort_unstable();
+        let n_sorted = Self::n_sorted_buckets(self.fingerprint_bits);
+        let pair_bits = Self::pair_bits(self.fingerprint_bits);
+        let offset = bucket / 2 * pair_bits as usize;
+        let pair = self.read_bits(offset, pair_bits);
+        let (high, low) = (pair / n_sorted % n_sorted, pair % n_sorted);
+        let pair = match bucket % 2 {
+            0 => high * n_sorted + rank_multiset(&fingerprints),
+            _ => rank_multiset(&fingerprints) * n_sorted + low,
+        };
+        self.write_bits(offset, pair_bits, pair);
+    }
+
+    /// The `width`-bit field starting at bit `offset` of the table, least significant bit first.
+    fn read_bits(&self, offset: usize, width: u32) -> u128 {
+        let mut value = 0;
+        let mut done = 0;
+        while done < width {
+            let (word, shift) = ((offset + done as usize) / 64, ((offset + done as usize) % 64) as u32);
+            let n = (64 - shift).min(width - done);
+            value |= (((self.words[word] >> shift) & low_bits(n)) as u128) << done;
+            done += n;
+        }
+        value
+    }
+
+    fn write_bits(&mut self, offset: usize, width: u32, value: u128) {
+        let mut done = 0;
+        while done < width {
+            let (word, shift) = ((offset + done as usize) / 64, ((offset + done as usize) % 64) as u32);
+            let n = (64 - shift).min(width - done);
+            let bits = (value >> done) as u64 & low_bits(n);
+            self.words[word] = (self.words[word] & !(low_bits(n) << shift)) | (bits << shift);
+            done += n;
+        }
+    }
+}
+
+fn low_bits(n: u32) -> u64 {
+    if n == 64 { u64::MAX } else { (1 << n) - 1 }
+}
+
+/// `C(n, k)` for the small `k` used here.
+fn binomial(n: u128, k: u32) -> u128 {
+    if n < k as u128 {
+        return 0;
+    }
+    (0..k as u128).fold(1, |product, i| product * (n - i) / (i + 1))
+}
+
+/// Position of the sorted `fingerprints` among all sorted multisets of four: the rank of the
+/// combination `fingerprints[i] + i` in the combinatorial number system.
+fn rank_multiset(fingerprints: &[u16; SORTED_BUCKET_SIZE]) -> u128 {
+    fingerprints.iter().enumerate().map(|(i, &fingerprint)| binomial(fingerprint as u128 + i as u128, i as u32 + 1)).sum()
+}
+
+fn unrank_multiset(mut rank: u128, fingerprint_bits: u32) -> [u16; SORTED_BUCKET_SIZE] {
+    let mut fingerprints = [EMPTY; SORTED_BUCKET_SIZE];
+    for i in (0..SORTED_BUCKET_SIZE).rev() {
+        // The largest combination element `c <= max` with `C(c, i + 1) <= rank`.
+        let k = i as u32 + 1;
+        let (mut low, mut high) = (i as u128, (1u128 << fingerprint_bits) - 1 + i as u128);
+        while low < high {
+            let middle = (low + high).div_ceil(2);
+            if binomial(middle, k) <= rank { low = middle } else { high = middle - 1 }
+        }
+        rank -= binomial(low, k);
+        fingerprints[i] = (low - i as u128) as u16;
+    }
+    fingerprints
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::params::BloomParams;
+
+    #[test]
+    fn test_cuckoo_filter_put_contains_remove() {
+        let mut filter = CuckooFilter::new(0.01, 10000);
+        (0..10000u64).for_each(|n| filter.put(n).unwrap());
+        assert_eq!(filter.len(), 10000);
+        assert!((0..10000u64).all(|n| filter.contains(n)));
+
+        // 10-bit fingerprints at 95% load: expect 2 * 4 * 0.95 / 1024 * 10000 = 74, sigma 9.
+        let false_positives = (10000..20000u64).filter(|&n| filter.contains(n)).count();
+        assert!(false_positives < 110, "{}", false_positives);
+
+        (0..5000u64).for_each(|n| filter.remove(n).unwrap());
+        assert_eq!(filter.len(), 5000);
+        assert!((5000..10000u64).all(|n| filter.contains(n)));
+    }
+
+    #[test]
+    fn test_cuckoo_filter_reports_full_without_losing_items() {
+        let mut filter = CuckooFilter::with_options(1, 64, 8, 2);
+        let mut inserted = vec![];
+        let mut n = 0u64;
+        while filter.put(n).is_ok() {
+            inserted.push(n);
+            n += 1;
+        }
+
+        assert!(filter.load_factor() > 0.5);
+        assert_eq!(filter.len(), inserted.len());
+        assert!(inserted.iter().all(|n| filter.contains(n)));
+    }
+
+    #[test]
+    fn test_cuckoo_filter_size_against_bloom_filter() {
+        for &rate in [0.03, 0.01, 0.001].iter() {
+            let filter = CuckooFilter::new(rate, 1_000_000);
+            let bloom = BloomParams::for_items_and_fpr(1_000_000, rate);
+            assert!(filter.memory_bytes() < bloom.memory_bytes(), "{} {} {}", rate, filter.memory_bytes(), bloom.memory_bytes());
+        }
+        // 20 buckets of four 8-bit fingerprints: 10 pairs of 55 bits, 72 bytes against 80 unsorted.
+        assert_eq!(CuckooFilter::new(0.03, 1_000_000).fingerprint_bits(), 8);
+        assert_eq!(CuckooFilter::with_options(0, 76, 8, 4).memory_bytes(), 72);
+    }
+
+    #[test]
+    fn test_cuckoo_filter_sorted_buckets() {
+        // All C(16 + 3, 4) multisets of 4-bit fingerprints, each sorted and ranked back to itself.
+        for rank in 0..CuckooFilter::n_sorted_buckets(4) {
+            let fingerprints = unrank_multiset(rank, 4);
+            assert!(fingerprints.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", fingerprints);
+            assert_eq!(rank_multiset(&fingerprints), rank);
+        }
+        assert_eq!(unrank_multiset(0, 16), [EMPTY; 4]);
+        let largest = [u16::MAX; 4];
+        assert_eq!(rank_multiset(&largest), CuckooFilter::n_sorted_buckets(16) - 1);
+        assert_eq!(unrank_multiset(CuckooFilter::n_sorted_buckets(16) - 1, 16), largest);
+        assert_eq!(CuckooFilter::pair_bits(16), 119);
+
+        // Fill every slot of a small table, with fingerprints that repeat within buckets, and
+        // check that writing one bucket leaves the other bucket of its pair alone.
+        let mut filter = CuckooFilter::with_options(5, 19, 4, 4);
+        for slot in (0..filter.capacity()).rev() {
+            assert!(filter.insert_into(slot / 4, (slot % 3 + 1) as u16 * 5));
+        }
+        for bucket in 0..filter.n_buckets {
+            let mut expected: Vec<u16> = (0..4).map(|i| ((bucket * 4 + i) % 3 + 1) as u16 * 5).collect();
+            expected.sort_unstable();
+            assert_eq!(filter.read_bucket(bucket).to_vec(), expected);
+            assert!(!filter.insert_into(bucket, 1));
+        }
+    }
+
+    #[test]
+    fn test_cuckoo_filter_packed_fingerprints() {
+        // 95-bit fields for pairs of 13-bit buckets straddle word boundaries, and 7 buckets is
+        // neither a power of two nor even: 4 fields, 380 bits, 6 words.
+        let mut filter = CuckooFilter::with_options(4, 26, 13, 4);
+        assert_eq!(filter.capacity(), 28);
+        assert_eq!(filter.memory_bytes(), 48);
+
+        (0..20u64).for_each(|n| filter.put(n).unwrap());
+        assert!((0..20u64).all(|n| filter.contains(n)));
+        (0..10u64).for_each(|n| filter.remove(n).unwrap());
+        assert!((10..20u64).all(|n| filter.contains(n)));
+        assert_eq!(filter.len(), 10);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_cuckoo_filter_serde_checks_dimensions() {
+        let mut filter = CuckooFilter::with_options(1, 100, 12, 4);
+        (0..100u64).for_each(|n| filter.put(n).unwrap());
+        let json = serde_json::to_value(&filter).unwrap();
+        let restored: CuckooFilter = serde_json::from_value(json.clone()).unwrap();
+        assert!((0..100u64).all(|n| restored.contains(n)));
+
+        let mut no_buckets = json.clone();
+        no_buckets["n_buckets"] = 0.into();
+        assert!(serde_json::from_value::<CuckooFilter>(no_buckets).is_err());
+        let mut short_table = json;
+        short_table["words"].as_array_mut().unwrap().pop();
+        assert!(serde_json::from_value::<CuckooFilter>(short_table).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use std::fmt;
+use std::io;
+
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnsupportedHashScheme(u8),
+    ChecksumMismatch { expected: u32, actual: u32 },
+    Corrupt(&'static str),
+    Incompatible(&'static str),
+    NotPresent,
+    Full,
+    BuildFailed(&'static str),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "io error: {}", err),
+            Error::BadMagic => write!(f, "not a filter file: bad magic bytes"),
+            Error::UnsupportedVersion(version) => write!(f, "unsupported format version {}", version),
+            Error::UnsupportedHashScheme(scheme) => write!(f, "unsupported hash scheme {}", scheme),
+            Error::ChecksumMismatch { expected, actual } =>
+                write!(f, "checksum mismatch: expected {:#010x}, got {:#010x}", expected, actual),
+            Error::Corrupt(reason) => write!(f, "corrupt filter data: {}", reason),
+            Error::Incompatible(reason) => write!(f, "incompatible filters: {}", reason),
+            Error::NotPresent => write!(f, "item is not in the filter"),
+            Error::Full => write!(f, "filter is full"),
+            Error::BuildFailed(reason) => write!(f, "could not build filter: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
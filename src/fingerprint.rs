@@ -0,0 +1,39 @@
+use std::fmt::Debug;
+use std::ops::BitXor;
+
+
+/// Unsigned integer fingerprints stored by the static (xor and binary fuse) filters.
+pub trait Fingerprint: Copy + Default + Eq + Debug + BitXor<Output = Self> {
+    const BITS: u32;
+
+    /// Folds a 64-bit key hash down to a fingerprint.
+    fn from_hash(hash: u64) -> Self;
+}
+
+macro_rules! impl_fingerprint {
+    ($($t:ty),*) => {$(
+        impl Fingerprint for $t {
+            const BITS: u32 = <$t>::BITS;
+
+            fn from_hash(hash: u64) -> Self {
+                (hash ^ (hash >> 32)) as $t
+            }
+        }
+    )*};
+}
+
+impl_fingerprint!(u8, u16, u32);
+
+/// The 64-bit finalizer of MurmurHash3, used to re-mix key hashes with a construction seed.
+pub(crate) fn mix64(mut hash: u64) -> u64 {
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+/// Maps a 32-bit value uniformly onto `0..n` without a division (Lemire's fast range).
+pub(crate) fn reduce(hash: u32, n: u32) -> u32 {
+    ((hash as u64 * n as u64) >> 32) as u32
+}
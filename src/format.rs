@@ -0,0 +1,115 @@
+//! On-disk layout shared by every persisted filter:
+//!
+//! | offset | size | field                                         |
+//! |--------|------|-----------------------------------------------|
+//! | 0      | 4    | magic `b"FLTR"`                               |
+//! | 4      | 2    | format version (little-endian)                |
+//! | 6      | 1    | hash scheme                                   |
+//! | 7      | 1    | reserved, zero                                |
+//! | 8      | 2    | `n_hashes` (little-endian)                    |
+//! | 10     | 2    | reserved, zero                                |
+//! | 12     | 8    | seed (little-endian)                          |
+//! | 20     | 8    | `n_bits` (little-endian)                      |
+//! | 28     | n    | bits, `ceil(n_bits / 8)` bytes, MSB first     |
+//! | 28 + n | 4    | CRC-32 of everything before it (little-endian)|
+//!
+//! Hash scheme 1 is the only one: bit `i` of the payload is set for a key whose encoding is
+//! the bytes `b` when `i` is one of the `n_hashes` probes `hash::probes(h1, h2, n_hashes,
+//! n_bits)`, where `(h1, h2)` is the 128-bit SipHash-2-4 of exactly `b` keyed by `(seed, 0)`.
+//! That is what `BloomFilter::put_bytes` computes on every platform. Keys put through the
+//! `Hash`-based `put` are hashed from whatever bytes their `Hash` impl produces on the
+//! writing platform, so files meant to be read elsewhere must be filled with `put_bytes`.
+
+use crate::error::{Error, Result};
+use std::convert::TryInto;
+
+
+pub const MAGIC: [u8; 4] = *b"FLTR";
+pub const VERSION: u16 = 1;
+/// 128-bit SipHash-2-4 of the key's byte encoding keyed by the seed, expanded with enhanced
+/// double hashing (see [`crate::hash::hash128_bytes`] and [`crate::hash::probes`]).
+pub const HASH_SCHEME_SIPHASH24: u8 = 1;
+pub const HEADER_LEN: usize = 28;
+pub const CHECKSUM_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    pub n_hashes: u16,
+    pub n_bits: usize,
+    pub seed: u64,
+}
+
+impl Header {
+    pub fn payload_len(&self) -> usize {
+        self.n_bits.div_ceil(8)
+    }
+
+    pub fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0u8; HEADER_LEN];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        bytes[6] = HASH_SCHEME_SIPHASH24;
+        bytes[8..10].copy_from_slice(&self.n_hashes.to_le_bytes());
+        bytes[12..20].copy_from_slice(&self.seed.to_le_bytes());
+        bytes[20..28].copy_from_slice(&(self.n_bits as u64).to_le_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Header> {
+        if bytes.len() < HEADER_LEN {
+            return Err(Error::Corrupt("truncated header"));
+        }
+        if bytes[0..4] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        let version = u16::from_le_bytes(bytes[4..6].try_into().unwrap());
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        if bytes[6] != HASH_SCHEME_SIPHASH24 {
+            return Err(Error::UnsupportedHashScheme(bytes[6]));
+        }
+
+        let n_hashes = u16::from_le_bytes(bytes[8..10].try_into().unwrap());
+        let seed = u64::from_le_bytes(bytes[12..20].try_into().unwrap());
+        let n_bits = u64::from_le_bytes(bytes[20..28].try_into().unwrap());
+        if n_hashes == 0 || n_bits == 0 {
+            return Err(Error::Corrupt("filter has no hashes or no bits"));
+        }
+        if n_bits > usize::MAX as u64 / 2 {
+            return Err(Error::Corrupt("bit count does not fit in memory"));
+        }
+
+        Ok(Header { n_hashes, n_bits: n_bits as usize, seed })
+    }
+}
+
+pub fn checksum(header: &[u8], payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(header);
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+/// Validates a complete serialized filter and returns its header and bit payload.
+pub fn split(bytes: &[u8]) -> Result<(Header, &[u8])> {
+    let header = Header::decode(bytes)?;
+    let payload_end = HEADER_LEN + header.payload_len();
+    if bytes.len() != payload_end + CHECKSUM_LEN {
+        return Err(Error::Corrupt("length does not match header"));
+    }
+
+    let payload = &bytes[HEADER_LEN..payload_end];
+    let expected = u32::from_le_bytes(bytes[payload_end..].try_into().unwrap());
+    let actual = checksum(&bytes[..HEADER_LEN], payload);
+    if expected != actual {
+        return Err(Error::ChecksumMismatch { expected, actual });
+    }
+
+    let padding_bits = header.payload_len() * 8 - header.n_bits;
+    if padding_bits > 0 && payload[payload.len() - 1] & ((1u8 << padding_bits) - 1) != 0 {
+        return Err(Error::Corrupt("padding bits are set"));
+    }
+
+    Ok((header, payload))
+}
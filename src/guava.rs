@@ -0,0 +1,264 @@
+use crate::error::{Error, Result};
+use crate::hash;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::f64::consts::LN_2;
+use std::io::{Read, Write};
+
+
+/// Ordinal of `BloomFilterStrategies.MURMUR128_MITZ_64` in Guava's stream format.
+pub const MURMUR128_MITZ_64: u8 = 1;
+const HEADER_LEN: usize = 6;
+
+/// A value as Guava's `Funnels` feed it into Murmur3: `integerFunnel()` and `longFunnel()`
+/// write little-endian bytes, `stringFunnel(UTF_8)` the UTF-8 bytes and `byteArrayFunnel()`
+/// the raw bytes.
+pub trait Funnel {
+    fn murmur3_hash(&self) -> (u64, u64);
+}
+
+impl Funnel for i32 {
+    fn murmur3_hash(&self) -> (u64, u64) {
+        hash::murmur3_x64_128(&self.to_le_bytes(), 0)
+    }
+}
+
+impl Funnel for i64 {
+    fn murmur3_hash(&self) -> (u64, u64) {
+        hash::murmur3_x64_128(&self.to_le_bytes(), 0)
+    }
+}
+
+impl Funnel for [u8] {
+    fn murmur3_hash(&self) -> (u64, u64) {
+        hash::murmur3_x64_128(self, 0)
+    }
+}
+
+impl Funnel for str {
+    fn murmur3_hash(&self) -> (u64, u64) {
+        hash::murmur3_x64_128(self.as_bytes(), 0)
+    }
+}
+
+/// Bloom filter laid out and hashed like Guava's `com.google.common.hash.BloomFilter` with
+/// the `MURMUR128_MITZ_64` strategy, so filters move between the JVM and here through
+/// `BloomFilter.writeTo` / `readFrom` and `write_to` / `read_from`.
+///
+/// The `i`-th probe of a value is bit `((h1 + i * h2) & i64::MAX) % n_bits` of its Murmur3
+/// hash, and bit `b` lives at `1 << (b % 64)` in word `b / 64`. The stream is the strategy
+/// ordinal, the hash count, the number of words as a big-endian `i32` and then the words as
+/// big-endian `i64`s.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "GuavaBloomFilterFields"))]
+pub struct GuavaBloomFilter {
+    n_hashes: u8,
+    data: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct GuavaBloomFilterFields {
+    n_hashes: u8,
+    data: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<GuavaBloomFilterFields> for GuavaBloomFilter {
+    type Error = Error;
+
+    fn try_from(fields: GuavaBloomFilterFields) -> Result<Self> {
+        let GuavaBloomFilterFields { n_hashes, data } = fields;
+        if n_hashes == 0 || data.is_empty() {
+            return Err(Error::Corrupt("a Bloom filter needs at least one word and one hash"));
+        }
+        Ok(GuavaBloomFilter { n_hashes, data })
+    }
+}
+
+impl GuavaBloomFilter {
+    /// Sized like `BloomFilter.create(funnel, expected_insertions, fpp)`: `-n ln(p) / ln(2)^2`
+    /// bits, rounded up to whole words, and `round(m / n * ln(2))` hashes.
+    pub fn new(false_positive_rate: f64, expected_insertions: u64) -> Self {
+        assert!(false_positive_rate > 0.0 && false_positive_rate < 1.0, "false positive rate must be in (0, 1)");
+        let n = expected_insertions.max(1) as f64;
+        let n_bits = (-n * false_positive_rate.ln() / (LN_2 * LN_2)) as u64;
+        let n_hashes = (n_bits as f64 / n * LN_2).round().clamp(1.0, u8::MAX as f64) as u8;
+
+        GuavaBloomFilter::with_dimensions(n_bits.div_ceil(64).max(1) as usize, n_hashes)
+    }
+
+    pub fn with_dimensions(n_words: usize, n_hashes: u8) -> Self {
+        assert!(n_words > 0 && n_hashes > 0, "a Bloom filter needs at least one word and one hash");
+        assert!(n_words <= i32::MAX as usize, "Guava cannot hold more than i32::MAX words");
+        GuavaBloomFilter { n_hashes, data: vec![0; n_words] }
+    }
+
+    pub fn put<V: Funnel + ?Sized>(&mut self, value: &V) {
+        let (h1, h2) = value.murmur3_hash();
+        self.put_hash(h1, h2);
+    }
+
+    pub fn contains<V: Funnel + ?Sized>(&self, value: &V) -> bool {
+        let (h1, h2) = value.murmur3_hash();
+        self.contains_hash(h1, h2)
+    }
+
+    /// Inserts a Murmur3 hash computed elsewhere, e.g. over a funnel not covered by `Funnel`.
+    pub fn put_hash(&mut self, h1: u64, h2: u64) {
+        for bit in self.probes(h1, h2) {
+            self.data[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    pub fn contains_hash(&self, h1: u64, h2: u64) -> bool {
+        self.probes(h1, h2).all(|bit| self.data[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    pub fn n_hashes(&self) -> u8 {
+        self.n_hashes
+    }
+
+    pub fn n_bits(&self) -> usize {
+        self.data.len() * 64
+    }
+
+    /// Guava's `expectedFpp()`: the fraction of set bits to the power of the hash count.
+    pub fn current_false_positive_rate(&self) -> f64 {
+        let set: u32 = self.data.iter().map(|word| word.count_ones()).sum();
+        (set as f64 / self.n_bits() as f64).powi(self.n_hashes as i32)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + 8 * self.data.len());
+        bytes.push(MURMUR128_MITZ_64);
+        bytes.push(self.n_hashes);
+        bytes.extend_from_slice(&(self.data.len() as i32).to_be_bytes());
+        self.data.iter().for_each(|word| bytes.extend_from_slice(&word.to_be_bytes()));
+        bytes
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_LEN {
+            return Err(Error::Corrupt("truncated Guava header"));
+        }
+        let n_words = Self::check_header(&bytes[..HEADER_LEN])?;
+        if bytes.len() != HEADER_LEN + 8 * n_words {
+            return Err(Error::Corrupt("Guava bit array length does not match the header"));
+        }
+
+        let data = bytes[HEADER_LEN..].chunks_exact(8).map(|word| {
+            let mut buffer = [0u8; 8];
+            buffer.copy_from_slice(word);
+            u64::from_be_bytes(buffer)
+        }).collect();
+        Ok(GuavaBloomFilter { n_hashes: bytes[1], data })
+    }
+
+    /// Reads exactly one filter, leaving anything after it in `reader`, as Guava's `readFrom`
+    /// does.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
+        let mut bytes = vec![0u8; HEADER_LEN];
+        reader.read_exact(&mut bytes)?;
+        let n_words = Self::check_header(&bytes)?;
+
+        let remaining = 8 * n_words as u64;
+        reader.take(remaining).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != HEADER_LEN as u64 + remaining {
+            return Err(Error::Corrupt("truncated Guava bit array"));
+        }
+        GuavaBloomFilter::from_bytes(&bytes)
+    }
+
+    /// Number of words announced by a header.
+    fn check_header(header: &[u8]) -> Result<usize> {
+        if header[0] != MURMUR128_MITZ_64 {
+            return Err(Error::UnsupportedHashScheme(header[0]));
+        }
+        if header[1] == 0 {
+            return Err(Error::Corrupt("Guava filter has no hash functions"));
+        }
+        let n_words = i32::from_be_bytes([header[2], header[3], header[4], header[5]]);
+        if n_words <= 0 {
+            return Err(Error::Corrupt("Guava bit array is empty"));
+        }
+        Ok(n_words as usize)
+    }
+
+    fn probes(&self, h1: u64, h2: u64) -> impl Iterator<Item = usize> {
+        let n_bits = self.n_bits() as u64;
+        (0..self.n_hashes as u64).map(move |i| {
+            let combined = h1.wrapping_add(i.wrapping_mul(h2));
+            ((combined & i64::MAX as u64) % n_bits) as usize
+        })
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // To be written by tests/fixtures/guava/GenerateFixtures.java with Guava 33.3.1-jre. The
+    // checked-in files predate that generator and have not yet been regenerated with Guava.
+    const STRINGS: &[u8] = include_bytes!("../tests/fixtures/guava/strings.bin");
+    const LONGS: &[u8] = include_bytes!("../tests/fixtures/guava/longs.bin");
+
+    #[test]
+    fn test_guava_bloom_filter_reads_fixtures() {
+        let strings = GuavaBloomFilter::read_from(STRINGS).unwrap();
+        assert_eq!((strings.n_hashes(), strings.n_bits()), (7, 9600));
+        assert!((0..1000).all(|n| strings.contains(format!("key-{}", n).as_str())));
+        assert!(strings.contains("naïve") && strings.contains("naïve".as_bytes()));
+        // 1001 items in 9600 bits with 7 hashes: expect 100 of 10000, sigma 10.
+        let false_positives = (1000..11000).filter(|n| strings.contains(format!("key-{}", n).as_str())).count();
+        assert!(false_positives < 130, "{}", false_positives);
+
+        let longs = GuavaBloomFilter::from_bytes(LONGS).unwrap();
+        assert_eq!((longs.n_hashes(), longs.n_bits()), (10, 7232));
+        assert!((0..500i64).all(|n| longs.contains(&(n * 7))));
+        // 500 items in 7232 bits with 10 hashes: expect 8 of the 8571 absent values.
+        let false_positives = (0..10000i64).filter(|n| n % 7 != 0 && longs.contains(n)).count();
+        assert!(false_positives < 20, "{}", false_positives);
+    }
+
+    #[test]
+    fn test_guava_bloom_filter_writes_fixtures() {
+        let mut strings = GuavaBloomFilter::new(0.01, 1000);
+        (0..1000).for_each(|n| strings.put(format!("key-{}", n).as_str()));
+        strings.put("naïve");
+        assert!(strings.to_bytes() == STRINGS);
+
+        let mut longs = GuavaBloomFilter::new(0.001, 500);
+        (0..500i64).for_each(|n| longs.put(&(n * 7)));
+        let mut written = vec![];
+        longs.write_to(&mut written).unwrap();
+        assert!(written == LONGS);
+    }
+
+    #[test]
+    fn test_guava_bloom_filter_rejects_bad_streams() {
+        let mut other_strategy = LONGS.to_vec();
+        other_strategy[0] = 0;
+        assert!(matches!(GuavaBloomFilter::from_bytes(&other_strategy), Err(Error::UnsupportedHashScheme(0))));
+
+        let mut no_hashes = LONGS.to_vec();
+        no_hashes[1] = 0;
+        assert!(matches!(GuavaBloomFilter::from_bytes(&no_hashes), Err(Error::Corrupt(_))));
+        assert!(matches!(GuavaBloomFilter::read_from(&LONGS[..LONGS.len() - 1]), Err(Error::Corrupt(_))));
+        assert!(GuavaBloomFilter::from_bytes(&[1, 3, 0, 0, 0, 0]).is_err());
+
+        let mut followed = LONGS.to_vec();
+        followed.extend_from_slice(b"rest");
+        let mut reader = &followed[..];
+        assert!(GuavaBloomFilter::read_from(&mut reader).is_ok());
+        assert_eq!(reader, b"rest");
+    }
+}
@@ -0,0 +1,329 @@
+use crate::fingerprint;
+use siphasher::sip128::{Hasher128, SipHasher24};
+use std::hash::{Hash, Hasher};
+use twox_hash::XxHash64;
+
+
+/// Hashes `value` with 128-bit SipHash-2-4 keyed by `(seed, 0)`.
+///
+/// The output depends on the bytes the value's `Hash` impl feeds into the hasher, which
+/// std only keeps the same for one target and toolchain: lengths and `usize` values are
+/// written at native width and endianness. Use `hash128_bytes` for anything hashed in one
+/// process and looked up in another.
+pub fn hash128<T: Hash + ?Sized>(value: &T, seed: u64) -> (u64, u64) {
+    let mut hasher = SipHasher24::new_with_keys(seed, 0);
+    value.hash(&mut hasher);
+    let hash = hasher.finish128();
+    (hash.h1, hash.h2)
+}
+
+/// 128-bit SipHash-2-4 of exactly `bytes`, keyed by `(seed, 0)`, as the two little-endian
+/// halves of the output. Nothing else is hashed, so the result is the same on every
+/// platform and matches any other SipHash-2-4-128 implementation.
+pub fn hash128_bytes(bytes: &[u8], seed: u64) -> (u64, u64) {
+    let mut hasher = SipHasher24::new_with_keys(seed, 0);
+    hasher.write(bytes);
+    let hash = hasher.finish128();
+    (hash.h1, hash.h2)
+}
+
+/// Enhanced double hashing (Dillinger & Manolios, 2004): starting from `h1 (mod n_slots)`,
+/// each probe steps by `h2 (mod n_slots)` and the step grows by `i` after the `i`-th probe.
+/// Unlike plain `h1 + i * h2`, the probes do not all collapse onto one slot when `h2` is a
+/// multiple of `n_slots`.
+pub fn probes(h1: u64, h2: u64, n_probes: u16, n_slots: usize) -> impl Iterator<Item = usize> {
+    let n_slots = n_slots as u64;
+    (0..n_probes as u64).scan((h1 % n_slots, h2 % n_slots), move |(slot, step), i| {
+        let probe = *slot as usize;
+        *slot = (*slot + *step) % n_slots;
+        *step = (*step + i + 1) % n_slots;
+        Some(probe)
+    })
+}
+
+/// 64-bit xxHash of `bytes`. Unlike `hash128`, this hashes raw bytes rather than a `Hash`
+/// impl, for formats such as Parquet that specify the exact bytes to hash.
+pub fn xxhash64(bytes: &[u8], seed: u64) -> u64 {
+    let mut hasher = XxHash64::with_seed(seed);
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// 128-bit MurmurHash3 for x64 (`MurmurHash3_x64_128`) of `bytes`, as the halves `h1` and
+/// `h2`. Guava's `Hashing.murmur3_128()` computes the same, with its output bytes being `h1`
+/// then `h2`, little-endian.
+pub fn murmur3_x64_128(bytes: &[u8], seed: u32) -> (u64, u64) {
+    const C1: u64 = 0x87c3_7b91_1142_53d5;
+    const C2: u64 = 0x4cf5_ad43_2745_937f;
+    let mix_k1 = |k1: u64| k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    let mix_k2 = |k2: u64| k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+    let (mut h1, mut h2) = (seed as u64, seed as u64);
+
+    let mut blocks = bytes.chunks_exact(16);
+    for block in &mut blocks {
+        h1 ^= mix_k1(le_u64(&block[..8]));
+        h1 = h1.rotate_left(27).wrapping_add(h2).wrapping_mul(5).wrapping_add(0x52dc_e729);
+        h2 ^= mix_k2(le_u64(&block[8..]));
+        h2 = h2.rotate_left(31).wrapping_add(h1).wrapping_mul(5).wrapping_add(0x3849_5ab5);
+    }
+
+    let tail = blocks.remainder();
+    if tail.len() > 8 {
+        h2 ^= mix_k2(le_u64(&tail[8..]));
+    }
+    if !tail.is_empty() {
+        h1 ^= mix_k1(le_u64(&tail[..tail.len().min(8)]));
+    }
+
+    h1 ^= bytes.len() as u64;
+    h2 ^= bytes.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fingerprint::mix64(h1);
+    h2 = fingerprint::mix64(h2);
+    h1 = h1.wrapping_add(h2);
+    (h1, h2.wrapping_add(h1))
+}
+
+/// LevelDB's 32-bit `Hash` (a MurmurHash1 variant) of `bytes`, which its Bloom filter policy
+/// calls with seed `0xbc9f1d34`.
+pub fn leveldb_hash(bytes: &[u8], seed: u32) -> u32 {
+    const M: u32 = 0xc6a4_a793;
+    let mut h = seed ^ (bytes.len() as u32).wrapping_mul(M);
+
+    let mut words = bytes.chunks_exact(4);
+    for word in &mut words {
+        h = h.wrapping_add(le_u64(word) as u32).wrapping_mul(M);
+        h ^= h >> 16;
+    }
+
+    let tail = words.remainder();
+    if !tail.is_empty() {
+        h = h.wrapping_add(le_u64(tail) as u32).wrapping_mul(M);
+        h ^= h >> 24;
+    }
+    h
+}
+
+const XXPH3_SECRET: [u8; 192] = [
+    0xb8, 0xfe, 0x6c, 0x39, 0x23, 0xa4, 0x4b, 0xbe, 0x7c, 0x01, 0x81, 0x2c, 0xf7, 0x21, 0xad, 0x1c,
+    0xde, 0xd4, 0x6d, 0xe9, 0x83, 0x90, 0x97, 0xdb, 0x72, 0x40, 0xa4, 0xa4, 0xb7, 0xb3, 0x67, 0x1f,
+    0xcb, 0x79, 0xe6, 0x4e, 0xcc, 0xc0, 0xe5, 0x78, 0x82, 0x5a, 0xd0, 0x7d, 0xcc, 0xff, 0x72, 0x21,
+    0xb8, 0x08, 0x46, 0x74, 0xf7, 0x43, 0x24, 0x8e, 0xe0, 0x35, 0x90, 0xe6, 0x81, 0x3a, 0x26, 0x4c,
+    0x3c, 0x28, 0x52, 0xbb, 0x91, 0xc3, 0x00, 0xcb, 0x88, 0xd0, 0x65, 0x8b, 0x1b, 0x53, 0x2e, 0xa3,
+    0x71, 0x64, 0x48, 0x97, 0xa2, 0x0d, 0xf9, 0x4e, 0x38, 0x19, 0xef, 0x46, 0xa9, 0xde, 0xac, 0xd8,
+    0xa8, 0xfa, 0x76, 0x3f, 0xe3, 0x9c, 0x34, 0x3f, 0xf9, 0xdc, 0xbb, 0xc7, 0xc7, 0x0b, 0x4f, 0x1d,
+    0x8a, 0x51, 0xe0, 0x4b, 0xcd, 0xb4, 0x59, 0x31, 0xc8, 0x9f, 0x7e, 0xc9, 0xd9, 0x78, 0x73, 0x64,
+    0xea, 0xc5, 0xac, 0x83, 0x34, 0xd3, 0xeb, 0xc3, 0xc5, 0x81, 0xa0, 0xff, 0xfa, 0x13, 0x63, 0xeb,
+    0x17, 0x0d, 0xdd, 0x51, 0xb7, 0xf0, 0xda, 0x49, 0xd3, 0x16, 0x55, 0x26, 0x29, 0xd4, 0x68, 0x9e,
+    0x2b, 0x16, 0xbe, 0x58, 0x7d, 0x47, 0xa1, 0xfc, 0x8f, 0xf8, 0xb8, 0xd1, 0x7a, 0xd0, 0x31, 0xce,
+    0x45, 0xcb, 0x3a, 0x8f, 0x95, 0x16, 0x04, 0x28, 0xaf, 0xd7, 0xfb, 0xca, 0xbb, 0x4b, 0x40, 0x7e,
+];
+
+/// RocksDB's `GetSliceHash64` of `bytes`: XXPH3, the preview release of 64-bit XXH3 that
+/// RocksDB froze for its filters, unseeded. It differs from the final XXH3 for some lengths
+/// and, by a RocksDB change, does not hash the empty input to zero.
+pub fn xxph3_64(bytes: &[u8]) -> u64 {
+    const PRIME32_1: u64 = 0x9e37_79b1;
+    const PRIME32_2: u64 = 0x85eb_ca77;
+    const PRIME32_3: u64 = 0xc2b2_ae3d;
+    const PRIME64_1: u64 = 0x9e37_79b1_85eb_ca87;
+    const PRIME64_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+    const PRIME64_3: u64 = 0x1656_67b1_9e37_79f9;
+    const PRIME64_4: u64 = 0x85eb_ca77_c2b2_ae63;
+    const PRIME64_5: u64 = 0x27d4_eb2f_1656_67c5;
+    let secret = &XXPH3_SECRET[..];
+    let len = bytes.len();
+    let fold = |lhs: u64, rhs: u64| {
+        let product = lhs as u128 * rhs as u128;
+        product as u64 ^ (product >> 64) as u64
+    };
+    let avalanche = |mut h: u64| {
+        h ^= h >> 37;
+        h = h.wrapping_mul(PRIME64_3);
+        h ^ (h >> 32)
+    };
+    let mix16 = |input: &[u8], secret: &[u8]| {
+        fold(le_u64(&input[..8]) ^ le_u64(&secret[..8]), le_u64(&input[8..16]) ^ le_u64(&secret[8..16]))
+    };
+
+    match len {
+        0 => fold(le_u64(&secret[..8]), PRIME64_2),
+        1..=3 => {
+            let combined = bytes[0] as u32 | (bytes[len >> 1] as u32) << 8
+                | (bytes[len - 1] as u32) << 16 | (len as u32) << 24;
+            let keyed = combined as u64 ^ le_u64(&secret[..4]);
+            avalanche(keyed.wrapping_mul(PRIME64_1))
+        }
+        4..=8 => {
+            let input = le_u64(&bytes[..4]) | le_u64(&bytes[len - 4..]) << 32;
+            let keyed = input ^ le_u64(&secret[..8]);
+            let mixed = (len as u64).wrapping_add((keyed ^ (keyed >> 51)).wrapping_mul(PRIME32_1));
+            avalanche((mixed ^ (mixed >> 47)).wrapping_mul(PRIME64_2))
+        }
+        9..=16 => {
+            let lo = le_u64(&bytes[..8]) ^ le_u64(&secret[..8]);
+            let hi = le_u64(&bytes[len - 8..]) ^ le_u64(&secret[8..16]);
+            avalanche((len as u64).wrapping_add(lo).wrapping_add(hi).wrapping_add(fold(lo, hi)))
+        }
+        17..=128 => {
+            let mut acc = (len as u64).wrapping_mul(PRIME64_1);
+            let pairs = (len - 1) / 32 + 1;
+            for i in 0..pairs {
+                acc = acc.wrapping_add(mix16(&bytes[16 * i..], &secret[32 * i..]));
+                acc = acc.wrapping_add(mix16(&bytes[len - 16 * (i + 1)..], &secret[32 * i + 16..]));
+            }
+            avalanche(acc)
+        }
+        129..=240 => {
+            let mut acc = (len as u64).wrapping_mul(PRIME64_1);
+            for i in 0..8 {
+                acc = acc.wrapping_add(mix16(&bytes[16 * i..], &secret[16 * i..]));
+            }
+            acc = avalanche(acc);
+            for i in 8..len / 16 {
+                acc = acc.wrapping_add(mix16(&bytes[16 * i..], &secret[16 * (i - 8) + 3..]));
+            }
+            acc = acc.wrapping_add(mix16(&bytes[len - 16..], &secret[136 - 17..]));
+            avalanche(acc)
+        }
+        _ => {
+            let mut acc = [
+                PRIME32_3, PRIME64_1, PRIME64_2, PRIME64_3, PRIME64_4, PRIME32_2, PRIME64_5, PRIME32_1,
+            ];
+            let accumulate = |acc: &mut [u64; 8], stripe: &[u8], secret: &[u8]| {
+                for ((lane, value), key) in acc.iter_mut().zip(stripe.chunks_exact(8)).zip(secret.chunks(8)) {
+                    let value = le_u64(value);
+                    let keyed = value ^ le_u64(key);
+                    *lane = lane.wrapping_add(value).wrapping_add((keyed & 0xffff_ffff) * (keyed >> 32));
+                }
+            };
+            let stripes_per_block = (secret.len() - 64) / 8;
+            let block_len = 64 * stripes_per_block;
+
+            let mut blocks = bytes.chunks_exact(block_len);
+            for block in &mut blocks {
+                for (n, stripe) in block.chunks_exact(64).enumerate() {
+                    accumulate(&mut acc, stripe, &secret[8 * n..]);
+                }
+                let scramble = &secret[secret.len() - 64..];
+                for (lane, key) in acc.iter_mut().zip(scramble.chunks_exact(8)) {
+                    *lane = (*lane ^ (*lane >> 47) ^ le_u64(key)).wrapping_mul(PRIME32_1);
+                }
+            }
+            for (n, stripe) in blocks.remainder().chunks_exact(64).enumerate() {
+                accumulate(&mut acc, stripe, &secret[8 * n..]);
+            }
+            if len % 64 != 0 {
+                accumulate(&mut acc, &bytes[len - 64..], &secret[secret.len() - 64 - 7..]);
+            }
+
+            let mut result = (len as u64).wrapping_mul(PRIME64_1);
+            for (i, lanes) in acc.chunks_exact(2).enumerate() {
+                let secret = &secret[11 + 16 * i..];
+                result = result.wrapping_add(fold(lanes[0] ^ le_u64(&secret[..8]), lanes[1] ^ le_u64(&secret[8..16])));
+            }
+            avalanche(result)
+        }
+    }
+}
+
+/// Little-endian value of up to eight bytes.
+fn le_u64(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |value, &byte| (value << 8) | byte as u64)
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash128_is_stable() {
+        assert_eq!(hash128(&"filters", 42), hash128(&"filters", 42));
+        assert_ne!(hash128(&"filters", 42), hash128(&"filters", 43));
+        assert_eq!(hash128(&42u64, 0), (12019655370092554255, 14601045575154395117));
+    }
+
+    #[test]
+    fn test_hash128_bytes_reference_values() {
+        // The SipHash paper's 128-bit vectors use the key 00..0f, i.e. a nonzero second half.
+        let mut hasher = SipHasher24::new_with_keys(0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908);
+        hasher.write(&[]);
+        assert_eq!(hasher.finish128().as_bytes(), [0xa3, 0x81, 0x7f, 0x04, 0xba, 0x25, 0xa8, 0xe6,
+                                                   0x6d, 0xf6, 0x72, 0x14, 0xc7, 0x55, 0x02, 0x93]);
+
+        assert_eq!(hash128_bytes(b"", 0), (9070429420880611664, 17650397300749173314));
+        assert_eq!(hash128_bytes(b"filters", 42), (13977268773355209225, 18289241435420501139));
+        let bytes: Vec<u8> = (0..20).collect();
+        assert_eq!(hash128_bytes(&bytes, 7), (4533908250198081281, 6451714655570331721));
+    }
+
+    #[test]
+    fn test_probes_in_range() {
+        let (h1, h2) = hash128(&7u64, 0);
+        let probes: Vec<usize> = probes(h1, h2, 5, 13).collect();
+        assert_eq!(probes.len(), 5);
+        assert!(probes.iter().all(|&p| p < 13));
+    }
+
+    #[test]
+    fn test_probes_zero_step() {
+        let mut probes: Vec<usize> = probes(5, 13 * 7, 4, 13).collect();
+        probes.sort_unstable();
+        probes.dedup();
+        assert!(probes.len() > 2, "{:?}", probes);
+    }
+
+    #[test]
+    fn test_xxhash64_reference_values() {
+        assert_eq!(xxhash64(b"", 0), 0xef46db3751d8e999);
+        assert_eq!(xxhash64(b"abc", 0), 0x44bc2cf5ad770999);
+    }
+
+    #[test]
+    fn test_murmur3_x64_128_reference_values() {
+        // From Guava's Murmur3Hash128Test.
+        assert_eq!(murmur3_x64_128(b"hell", 0), (0x629942693e10f867, 0x92db0b82baeb5347));
+        assert_eq!(murmur3_x64_128(b"hello", 1), (0xa78ddff5adae8d10, 0x128900ef20900135));
+        assert_eq!(murmur3_x64_128(b"The quick brown fox jumps over the lazy dog", 0),
+                   (0xe34bbc7bbc071b6c, 0x7a433ca9c49a9347));
+        assert_eq!(murmur3_x64_128(b"The quick brown fox jumps over the lazy cog", 0),
+                   (0x658ca970ff85269a, 0x43fee3eaa68e5c3e));
+        // Empty input, and a tail longer than eight bytes, checked against the murmur3 crate.
+        assert_eq!(murmur3_x64_128(b"", 0), (0, 0));
+        assert_eq!(murmur3_x64_128(b"0123456789abcdefXYZ", 7), (0xbaebbbd0af8e46ea, 0xf4d88ea08195f612));
+    }
+
+    #[test]
+    fn test_leveldb_hash_reference_values() {
+        // From LevelDB's hash_test.cc.
+        assert_eq!(leveldb_hash(b"", 0xbc9f1d34), 0xbc9f1d34);
+        assert_eq!(leveldb_hash(&[0x62], 0xbc9f1d34), 0xef1345c4);
+        assert_eq!(leveldb_hash(&[0xc3, 0x97], 0xbc9f1d34), 0x5b663814);
+        assert_eq!(leveldb_hash(&[0xe2, 0x99, 0xa5], 0xbc9f1d34), 0x323c078f);
+        assert_eq!(leveldb_hash(&[0xe1, 0x80, 0xb9, 0x32], 0xbc9f1d34), 0xed21633a);
+        // Longer inputs, checked against LevelDB 1.22's util/hash.cc.
+        let bytes: Vec<u8> = (0..64u32).map(|i| (i * 7 + 3) as u8).collect();
+        assert_eq!(leveldb_hash(&bytes[..9], 0xbc9f1d34), 0xa4967bcf);
+        assert_eq!(leveldb_hash(&bytes[..33], 0xbc9f1d34), 0x50446a29);
+    }
+
+    #[test]
+    fn test_xxph3_64_reference_values() {
+        // Checked against XXPH3_64bits from RocksDB 8.10's util/xxph3.h, one input per code
+        // path and around each boundary.
+        let bytes: Vec<u8> = (0..3000u32).map(|i| (i * 7 + 3) as u8).collect();
+        let expected = [
+            (0, 0x5342c3010fe1dd04), (1, 0x81b78b21c2ce4f18), (3, 0x6731a716813ee5f1),
+            (4, 0x3a5afd967acaf5c3), (8, 0xbcb56b6f8d4b12da), (9, 0x03787b4eec57bf4b),
+            (16, 0x81720cc0702edd73), (17, 0x0b515520f462e96f), (33, 0xd020587a3c72b988),
+            (97, 0x19e9c1871ecd39dc), (128, 0x8ea76d838ce7563f), (129, 0x3992d120c8c4677a),
+            (200, 0x4ddc3e63692b0196), (240, 0xdf41bc5fea3a61e8), (241, 0x91d10f0682af4ac3),
+            (1024, 0x7d54b94e043675ba), (2047, 0x35cfc47e7ecf32fb), (3000, 0x7c52298161bec0d4),
+        ];
+        for &(len, hash) in expected.iter() {
+            assert_eq!(xxph3_64(&bytes[..len]), hash, "{}", len);
+        }
+    }
+}
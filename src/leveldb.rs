@@ -0,0 +1,149 @@
+use crate::error::{Error, Result};
+use crate::hash;
+use crate::membership::MembershipQuery;
+
+
+/// Seed LevelDB's Bloom filter policy passes to its `Hash`.
+const SEED: u32 = 0xbc9f_1d34;
+/// Probe counts above this are reserved by LevelDB, which matches every key against them.
+const MAX_PROBES: u8 = 30;
+
+/// Filter block as written by LevelDB's `NewBloomFilterPolicy` (`leveldb.BuiltinBloomFilter2`):
+/// the bit array followed by one byte holding the number of probes. Each key is hashed once
+/// with LevelDB's 32-bit `Hash`, and the hash, advanced by itself rotated right by 17 bits,
+/// picks every probe modulo the number of bits.
+///
+/// This is the format of the per-data-block filters in LevelDB tables (and RocksDB's old
+/// block-based filters), so the bytes of one entry in a table's filter block can be queried
+/// directly.
+pub struct LevelDbBloomFilter {
+    data: Vec<u8>,
+}
+
+impl LevelDbBloomFilter {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 2 {
+            return Err(Error::Corrupt("LevelDB filter is shorter than two bytes"));
+        }
+        Ok(LevelDbBloomFilter { data: bytes.to_vec() })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn n_probes(&self) -> u8 {
+        self.data[self.data.len() - 1]
+    }
+
+    pub fn n_bits(&self) -> usize {
+        (self.data.len() - 1) * 8
+    }
+
+    /// Like LevelDB, treats filters with a reserved probe count as matching every key.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        if self.n_probes() > MAX_PROBES {
+            return true;
+        }
+        let bits = &self.data[..self.data.len() - 1];
+        Self::probes(hash::leveldb_hash(key, SEED), self.n_probes(), self.n_bits())
+            .all(|bit| bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    fn probes(hash: u32, n_probes: u8, n_bits: usize) -> impl Iterator<Item = usize> {
+        let delta = hash.rotate_right(17);
+        (0..n_probes as u32).map(move |i| (hash.wrapping_add(i.wrapping_mul(delta)) as usize) % n_bits)
+    }
+}
+
+impl MembershipQuery<[u8]> for LevelDbBloomFilter {
+    fn contains(&self, key: &[u8]) -> bool {
+        LevelDbBloomFilter::contains(self, key)
+    }
+}
+
+impl MembershipQuery<str> for LevelDbBloomFilter {
+    fn contains(&self, key: &str) -> bool {
+        LevelDbBloomFilter::contains(self, key.as_bytes())
+    }
+}
+
+/// Builds filters the way LevelDB's `NewBloomFilterPolicy(bits_per_key)` does: at least 64
+/// bits, and `bits_per_key * 0.69` probes, clamped to `1..=30`.
+pub struct LevelDbBloomBuilder {
+    bits_per_key: usize,
+    n_probes: u8,
+    hashes: Vec<u32>,
+}
+
+impl LevelDbBloomBuilder {
+    pub fn new(bits_per_key: usize) -> Self {
+        let n_probes = ((bits_per_key as f64 * 0.69) as usize).clamp(1, MAX_PROBES as usize) as u8;
+        LevelDbBloomBuilder { bits_per_key, n_probes, hashes: vec![] }
+    }
+
+    pub fn add_key(&mut self, key: &[u8]) {
+        self.hashes.push(hash::leveldb_hash(key, SEED));
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Filter over every key added so far, byte for byte what LevelDB writes for them.
+    pub fn finish(&self) -> LevelDbBloomFilter {
+        let n_bits = (self.hashes.len() * self.bits_per_key).max(64).div_ceil(8) * 8;
+        let mut data = vec![0u8; n_bits / 8 + 1];
+        for &hash in self.hashes.iter() {
+            for bit in LevelDbBloomFilter::probes(hash, self.n_probes, n_bits) {
+                data[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        data[n_bits / 8] = self.n_probes;
+        LevelDbBloomFilter { data }
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // Written by tests/fixtures/leveldb/generate_fixture.cc with LevelDB's own filter policy.
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/leveldb/bloom.bin");
+
+    #[test]
+    fn test_leveldb_bloom_filter_reads_fixture() {
+        let filter = LevelDbBloomFilter::from_bytes(FIXTURE).unwrap();
+        assert_eq!((filter.n_probes(), filter.n_bits()), (6, 10000));
+        assert!((0..1000).all(|n| filter.contains(format!("key-{}", n).as_bytes())));
+        // 1000 keys in 10000 bits with 6 probes: expect 84 of 10000, sigma 9.
+        let false_positives = (1000..11000).filter(|n| filter.contains(format!("key-{}", n).as_bytes())).count();
+        assert!(false_positives < 120, "{}", false_positives);
+    }
+
+    #[test]
+    fn test_leveldb_bloom_builder_matches_fixture() {
+        let mut builder = LevelDbBloomBuilder::new(10);
+        (0..1000).for_each(|n| builder.add_key(format!("key-{}", n).as_bytes()));
+        assert_eq!(builder.len(), 1000);
+        assert!(builder.finish().as_bytes() == FIXTURE);
+    }
+
+    #[test]
+    fn test_leveldb_bloom_edge_cases() {
+        let empty = LevelDbBloomBuilder::new(10).finish();
+        assert_eq!((empty.n_bits(), empty.n_probes()), (64, 6));
+        assert!(!empty.contains(b"anything"));
+        assert_eq!(LevelDbBloomBuilder::new(1).finish().n_probes(), 1);
+        assert_eq!(LevelDbBloomBuilder::new(100).finish().n_probes(), 30);
+
+        assert!(LevelDbBloomFilter::from_bytes(&[0]).is_err());
+        let reserved = LevelDbBloomFilter::from_bytes(&[0, 0, 0, 31]).unwrap();
+        assert!(reserved.contains(b"anything"));
+    }
+}
@@ -1 +1,26 @@
-pub mod bloom;
\ No newline at end of file
+pub mod binary_fuse;
+pub mod blocked;
+pub mod bloom;
+pub mod counting;
+pub mod counting_quotient;
+pub mod cuckoo;
+pub mod error;
+pub mod fingerprint;
+mod format;
+pub mod guava;
+pub mod hash;
+pub mod leveldb;
+pub mod membership;
+pub mod mmap;
+pub mod params;
+mod peeling;
+pub mod quotient;
+pub mod ribbon;
+pub mod rocksdb;
+pub mod scalable;
+pub mod split_block;
+pub mod stable;
+pub mod window;
+#[cfg(feature = "serde")]
+mod serde_support;
+pub mod xor;
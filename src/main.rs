@@ -1,12 +1,348 @@
 extern crate filters;
 
 use filters::bloom::BloomFilter;
+use filters::error::Error;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process;
+
+
+/// Seed for `build` unless `--seed` is given. A fixed default keeps filters built with the
+/// same `--fpr` and `--capacity` mergeable.
+const DEFAULT_SEED: u64 = 0;
+const DEFAULT_FPR: f64 = 0.01;
+
+const USAGE: &str = "usage:
+  filters build [--fpr RATE] [--capacity N] [--seed SEED] [--input FILE] --output FILE
+  filters query [--quiet] FILTER [KEY...]
+  filters info FILTER
+  filters merge --output FILE FILTER FILTER...
+
+build   puts every line of FILE (or stdin) into a new Bloom filter. --fpr defaults to 0.01 and
+        --capacity to the number of keys read, which means holding every key in memory until
+        the input ends.
+query   checks each KEY (or each line of stdin) and prints it with `true` or `false`. Exits
+        with 0 if every key may be present, 1 if any is certainly absent. --quiet only sets
+        the exit code.
+info    prints the filter's parameters, fill ratio and estimated number of keys.
+merge   writes the union of filters built with the same parameters and seed.
+
+Keys are hashed as exactly their bytes, without the line ending, so filters built on one
+platform can be queried on any other. Empty lines are skipped.";
+
+enum Failure {
+    Usage(String),
+    Io(String, io::Error),
+    Filter(String, Error),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            Failure::Io(path, err) => write!(f, "{}: {}", path, err),
+            Failure::Filter(path, err) => write!(f, "{}: {}", path, err),
+        }
+    }
+}
+
+/// Exit code, or what to report before exiting with 2.
+type Outcome = Result<i32, Failure>;
+
+fn usage<T>(message: &str) -> Result<T, Failure> {
+    Err(Failure::Usage(message.to_string()))
+}
+
+/// `--name value` pairs, with an empty value for flags.
+type Options = Vec<(String, String)>;
+
+/// Splits `args` into options and the remaining operands. `flags` name the options that take
+/// no value.
+fn parse_options(args: &[String], flags: &[&str]) -> Result<(Options, Vec<String>), Failure> {
+    let mut options = vec![];
+    let mut operands = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            operands.push(arg.clone());
+        } else if flags.contains(&arg.as_str()) {
+            options.push((arg.clone(), String::new()));
+        } else {
+            match args.next() {
+                Some(value) => options.push((arg.clone(), value.clone())),
+                None => return usage(&format!("{} needs a value", arg)),
+            }
+        }
+    }
+    Ok((options, operands))
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, Failure> {
+    value.parse().or_else(|_| usage(&format!("invalid value for {}: {}", name, value)))
+}
+
+/// Calls `f` with every non-empty line of `reader`, without its line ending.
+fn for_each_key<R: BufRead, F: FnMut(&[u8])>(mut reader: R, mut f: F) -> io::Result<()> {
+    let mut line = vec![];
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        if !line.is_empty() {
+            f(&line);
+        }
+    }
+}
+
+fn read_filter(path: &str) -> Result<BloomFilter, Failure> {
+    let file = File::open(path).map_err(|err| Failure::Io(path.to_string(), err))?;
+    BloomFilter::read_from(BufReader::new(file)).map_err(|err| Failure::Filter(path.to_string(), err))
+}
+
+fn write_filter(path: &str, filter: &BloomFilter) -> Result<(), Failure> {
+    let file = File::create(path).map_err(|err| Failure::Io(path.to_string(), err))?;
+    let mut writer = BufWriter::new(file);
+    filter.write_to(&mut writer).map_err(|err| Failure::Filter(path.to_string(), err))?;
+    writer.flush().map_err(|err| Failure::Io(path.to_string(), err))
+}
+
+fn build<R: BufRead>(args: &[String], stdin: R) -> Outcome {
+    let (options, operands) = parse_options(args, &[])?;
+    if !operands.is_empty() {
+        return usage(&format!("unexpected argument: {}", operands[0]));
+    }
+    let (mut fpr, mut capacity, mut seed, mut input, mut output) = (DEFAULT_FPR, None, DEFAULT_SEED, None, None);
+    for (name, value) in options.iter() {
+        match name.as_str() {
+            "--fpr" => fpr = parse_value(name, value)?,
+            "--capacity" => capacity = Some(parse_value(name, value)?),
+            "--seed" => seed = parse_value(name, value)?,
+            "--input" => input = Some(value.clone()),
+            "--output" => output = Some(value.clone()),
+            _ => return usage(&format!("unknown option for build: {}", name)),
+        }
+    }
+    if !(fpr > 0.0 && fpr < 1.0) {
+        return usage("--fpr must be between 0 and 1");
+    }
+    let output = match output {
+        Some(output) => output,
+        None => return usage("build needs --output"),
+    };
+
+    let reader: Box<dyn BufRead> = match &input {
+        Some(path) => Box::new(BufReader::new(File::open(path).map_err(|err| Failure::Io(path.clone(), err))?)),
+        None => Box::new(stdin),
+    };
+    let source = input.unwrap_or_else(|| "<stdin>".to_string());
+    let filter = match capacity {
+        Some(capacity) => {
+            let mut filter = BloomFilter::with_seed(seed, fpr, capacity);
+            for_each_key(reader, |key| filter.put_bytes(key)).map_err(|err| Failure::Io(source, err))?;
+            filter
+        }
+        None => {
+            let mut keys = vec![];
+            for_each_key(reader, |key| keys.push(key.to_vec())).map_err(|err| Failure::Io(source, err))?;
+            let mut filter = BloomFilter::with_seed(seed, fpr, keys.len().max(1) as u64);
+            keys.iter().for_each(|key| filter.put_bytes(key));
+            filter
+        }
+    };
+    write_filter(&output, &filter)?;
+    Ok(0)
+}
+
+fn query<R: BufRead, W: Write>(args: &[String], stdin: R, mut stdout: W) -> Outcome {
+    let (options, operands) = parse_options(args, &["--quiet"])?;
+    let mut quiet = false;
+    for (name, _) in options.iter() {
+        match name.as_str() {
+            "--quiet" => quiet = true,
+            _ => return usage(&format!("unknown option for query: {}", name)),
+        }
+    }
+    let (path, keys) = match operands.split_first() {
+        Some((path, keys)) => (path, keys),
+        None => return usage("query needs a filter file"),
+    };
+    let filter = read_filter(path)?;
+
+    let mut all_present = true;
+    let mut result = Ok(());
+    let mut check = |key: &[u8]| {
+        let present = filter.contains_bytes(key);
+        all_present &= present;
+        if !quiet && result.is_ok() {
+            result = stdout.write_all(key).and_then(|_| writeln!(stdout, "\t{}", present));
+        }
+    };
+    if keys.is_empty() {
+        for_each_key(stdin, &mut check).map_err(|err| Failure::Io("<stdin>".to_string(), err))?;
+    } else {
+        keys.iter().for_each(|key| check(key.as_bytes()));
+    }
+    result.and_then(|_| stdout.flush()).map_err(|err| Failure::Io("<stdout>".to_string(), err))?;
+    Ok(if all_present { 0 } else { 1 })
+}
+
+fn info<W: Write>(args: &[String], mut stdout: W) -> Outcome {
+    let path = match args {
+        [path] => path,
+        _ => return usage("info needs exactly one filter file"),
+    };
+    let filter = read_filter(path)?;
+
+    writeln!(stdout, "bits: {}", filter.n_bits())
+        .and_then(|_| writeln!(stdout, "hashes: {}", filter.n_hashes()))
+        .and_then(|_| writeln!(stdout, "seed: {}", filter.seed()))
+        .and_then(|_| writeln!(stdout, "fill ratio: {:.4}", filter.fill_ratio()))
+        .and_then(|_| writeln!(stdout, "estimated keys: {:.0}", filter.estimated_len()))
+        .and_then(|_| writeln!(stdout, "false positive rate: {:.6}", filter.current_false_positive_rate()))
+        .map_err(|err| Failure::Io("<stdout>".to_string(), err))?;
+    Ok(0)
+}
+
+fn merge(args: &[String]) -> Outcome {
+    let (options, operands) = parse_options(args, &[])?;
+    let mut output = None;
+    for (name, value) in options.iter() {
+        match name.as_str() {
+            "--output" => output = Some(value.clone()),
+            _ => return usage(&format!("unknown option for merge: {}", name)),
+        }
+    }
+    let output = match output {
+        Some(output) => output,
+        None => return usage("merge needs --output"),
+    };
+    if operands.len() < 2 {
+        return usage("merge needs at least two filter files");
+    }
+
+    let mut merged = read_filter(&operands[0])?;
+    for path in operands[1..].iter() {
+        merged.union(&read_filter(path)?).map_err(|err| Failure::Filter(path.clone(), err))?;
+    }
+    write_filter(&output, &merged)?;
+    Ok(0)
+}
+
+fn run<R: BufRead, W: Write>(args: &[String], stdin: R, mut stdout: W) -> Outcome {
+    match args.split_first() {
+        Some((command, args)) => match command.as_str() {
+            "build" => build(args, stdin),
+            "query" => query(args, stdin, stdout),
+            "info" => info(args, stdout),
+            "merge" => merge(args),
+            "help" | "--help" | "-h" => {
+                writeln!(stdout, "{}", USAGE).map_err(|err| Failure::Io("<stdout>".to_string(), err))?;
+                Ok(0)
+            }
+            _ => usage(&format!("unknown command: {}", command)),
+        },
+        None => usage("missing command"),
+    }
+}
 
 fn main() {
-    let words = vec!['a', 'b', 'c'];
-    let mut bloom = BloomFilter::new(0.1, 100);
-    words.iter().for_each(|c| bloom.put(c));
+    let args: Vec<String> = env::args().skip(1).collect();
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    match run(&args, stdin.lock(), stdout.lock()) {
+        Ok(code) => process::exit(code),
+        Err(failure) => {
+            eprintln!("filters: {}", failure);
+            process::exit(2);
+        }
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> String {
+        let path: PathBuf = env::temp_dir().join(format!("filters-cli-{}-{}", process::id(), name));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn run_with(args: &[&str], stdin: &str) -> (Result<i32, String>, String) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut stdout = vec![];
+        let outcome = run(&args, Cursor::new(stdin.as_bytes().to_vec()), &mut stdout).map_err(|failure| failure.to_string());
+        (outcome, String::from_utf8(stdout).unwrap())
+    }
+
+    #[test]
+    fn test_cli_build_query_info() {
+        let (keys, filter) = (temp_path("keys.txt"), temp_path("build.fltr"));
+        let lines: String = (0..1000).map(|n| format!("key-{}\r\n", n)).collect();
+        fs::write(&keys, lines + "\n").unwrap();
+
+        assert_eq!(run_with(&["build", "--fpr", "0.001", "--input", &keys, "--output", &filter], "").0, Ok(0));
+        let (outcome, output) = run_with(&["query", &filter, "key-7", "key-999"], "");
+        assert_eq!((outcome, output.as_str()), (Ok(0), "key-7\ttrue\nkey-999\ttrue\n"));
+        let (outcome, output) = run_with(&["query", "--quiet", &filter], "key-1\nnot-a-key\n");
+        assert_eq!((outcome, output.as_str()), (Ok(1), ""));
+
+        let (outcome, output) = run_with(&["info", &filter], "");
+        assert_eq!(outcome, Ok(0));
+        assert!(output.starts_with("bits: 14378\nhashes: 10\nseed: 0\n"), "{}", output);
+        let estimate: f64 = output.lines().find_map(|line| line.strip_prefix("estimated keys: ")).unwrap().parse().unwrap();
+        assert!((estimate - 1000.0).abs() < 50.0, "{}", estimate);
+        let built = BloomFilter::from_bytes(&fs::read(&filter).unwrap()).unwrap();
+        assert!((0..1000).all(|n| built.contains_bytes(format!("key-{}", n).as_bytes())));
+
+        fs::remove_file(keys).unwrap();
+        fs::remove_file(filter).unwrap();
+    }
+
+    #[test]
+    fn test_cli_build_from_stdin_and_merge() {
+        let (left, right, merged) = (temp_path("left.fltr"), temp_path("right.fltr"), temp_path("merged.fltr"));
+        let build = |path: &str, keys: &str| run_with(&["build", "--capacity", "100", "--output", path], keys).0;
+        assert_eq!(build(&left, "apple\npear\n"), Ok(0));
+        assert_eq!(build(&right, "plum\n"), Ok(0));
+
+        assert_eq!(run_with(&["merge", "--output", &merged, &left, &right], "").0, Ok(0));
+        let (outcome, output) = run_with(&["query", &merged], "apple\nplum\npear\n");
+        assert_eq!((outcome, output.as_str()), (Ok(0), "apple\ttrue\nplum\ttrue\npear\ttrue\n"));
+
+        let other = temp_path("other.fltr");
+        assert_eq!(run_with(&["build", "--capacity", "100", "--seed", "9", "--output", &other], "fig\n").0, Ok(0));
+        let (outcome, _) = run_with(&["merge", "--output", &merged, &left, &other], "");
+        assert!(outcome.unwrap_err().ends_with("incompatible filters: seeds differ"));
+
+        [left, right, merged, other].iter().for_each(|path| fs::remove_file(path).unwrap());
+    }
 
-    println!("n_bits: {}", bloom.n_bits());
-    println!("n_hashes: {}", bloom.n_hashes());
+    #[test]
+    fn test_cli_usage_errors() {
+        let missing = temp_path("missing.fltr");
+        for args in [&["frobnicate"][..], &["build", "--fpr", "2", "--output", "x"], &["build", "--fpr"],
+                     &["build"], &["query"], &["info"], &["merge", "--output", "x", "a"]].iter() {
+            let (outcome, _) = run_with(args, "");
+            assert!(outcome.unwrap_err().contains("usage:"), "{:?}", args);
+        }
+        let (outcome, output) = run_with(&["--help"], "");
+        assert_eq!(outcome, Ok(0));
+        assert!(output.starts_with("usage:"));
+        let (outcome, _) = run_with(&["info", &missing], "");
+        assert!(outcome.unwrap_err().starts_with(&missing));
+    }
 }
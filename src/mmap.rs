@@ -0,0 +1,89 @@
+use crate::bloom::BloomFilter;
+use crate::error::Result;
+use crate::format::{self, Header};
+use memmap2::Mmap;
+use std::fs::File;
+use std::hash::Hash;
+use std::path::Path;
+
+
+/// A read-only Bloom filter answering `contains` straight from a memory-mapped file
+/// written by `BloomFilter::write_to`, so processes mapping the same file share one
+/// page-cached copy of the bits.
+pub struct MmapBloomFilter {
+    header: Header,
+    mmap: Mmap,
+}
+
+impl MmapBloomFilter {
+    /// Maps the file and validates its header and checksum.
+    ///
+    /// The file must not be modified or truncated while it is mapped.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+        // Safety: the filter file is treated as immutable for as long as the mapping lives.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let (header, _payload) = format::split(&mmap)?;
+
+        Ok(MmapBloomFilter { header, mmap })
+    }
+
+    pub fn contains<T: Hash>(&self, value: T) -> bool {
+        BloomFilter::get_bits(self.header.seed, self.header.n_hashes, self.header.n_bits, &value)
+            .all(|bit| self.is_set(bit))
+    }
+
+    /// Looks up a key put with `BloomFilter::put_bytes`, possibly by another platform.
+    pub fn contains_bytes(&self, bytes: &[u8]) -> bool {
+        BloomFilter::get_bits_for_bytes(self.header.seed, self.header.n_hashes, self.header.n_bits, bytes)
+            .all(|bit| self.is_set(bit))
+    }
+
+    pub fn n_hashes(&self) -> u16 {
+        self.header.n_hashes
+    }
+
+    pub fn n_bits(&self) -> usize {
+        self.header.n_bits
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.header.seed
+    }
+
+    fn is_set(&self, bit: usize) -> bool {
+        self.mmap[format::HEADER_LEN + bit / 8] & (0x80 >> (bit % 8)) != 0
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::error::Error;
+    use std::fs;
+
+    #[test]
+    fn test_mmap_bloom_filter_matches_bloom_filter() {
+        let path = std::env::temp_dir().join(format!("filters-mmap-{}.bin", std::process::id()));
+        let mut bloom = BloomFilter::new(0.01, 1000);
+        (0..1000u64).for_each(|n| bloom.put(n));
+        bloom.put_bytes(b"shared");
+        bloom.write_to(File::create(&path).unwrap()).unwrap();
+
+        let mapped = MmapBloomFilter::open(&path).unwrap();
+        assert_eq!(mapped.n_hashes(), bloom.n_hashes());
+        assert_eq!(mapped.n_bits(), bloom.n_bits());
+        assert_eq!(mapped.seed(), bloom.seed());
+        assert!((0..10000u64).all(|n| mapped.contains(n) == bloom.contains(n)));
+        assert!(mapped.contains_bytes(b"shared"));
+        drop(mapped);
+
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[format::HEADER_LEN] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(MmapBloomFilter::open(&path), Err(Error::ChecksumMismatch { .. })));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
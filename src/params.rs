@@ -0,0 +1,124 @@
+//! Bloom filter sizing. With `n` expected items, `m` bits and `k` hash functions the
+//! false positive rate is `p = (1 - e^(-k * n / m))^k`, minimized by `k = (m / n) * ln 2`,
+//! which gives `m = -n * ln p / (ln 2)^2`. Since `k` must be an integer, `m` is then
+//! recomputed for the rounded `k` so the rate never exceeds the requested one.
+
+use std::f64::consts::LN_2;
+
+/// Upper bound on the hash count. Past it each extra probe costs more time than the
+/// accuracy it buys, and an empty or nearly empty budget would otherwise ask for thousands.
+pub const MAX_HASHES: u16 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomParams {
+    expected_items: u64,
+    n_bits: usize,
+    n_hashes: u16,
+}
+
+impl BloomParams {
+    /// Smallest filter holding `expected_items` at `false_positive_rate`.
+    pub fn for_items_and_fpr(expected_items: u64, false_positive_rate: f64) -> Self {
+        Self::check_rate(false_positive_rate);
+        let n_hashes = Self::hashes_for_rate(false_positive_rate);
+        let k = n_hashes as f64;
+        let n_bits = (-k * expected_items as f64 / (1.0 - false_positive_rate.powf(1.0 / k)).ln()).ceil();
+
+        BloomParams { expected_items, n_bits: (n_bits as usize).max(1), n_hashes }
+    }
+
+    /// Best hash count for `expected_items` in a fixed budget of `n_bits`.
+    pub fn for_items_and_bits(expected_items: u64, n_bits: usize) -> Self {
+        let n_bits = n_bits.max(1);
+
+        BloomParams { expected_items, n_bits, n_hashes: Self::optimal_hashes(expected_items, n_bits) }
+    }
+
+    /// How many items `n_bits` can hold while staying at `false_positive_rate`.
+    pub fn for_bits_and_fpr(n_bits: usize, false_positive_rate: f64) -> Self {
+        Self::check_rate(false_positive_rate);
+        let n_bits = n_bits.max(1);
+        let n_hashes = Self::hashes_for_rate(false_positive_rate);
+        let k = n_hashes as f64;
+        let expected_items = (-(n_bits as f64) * (1.0 - false_positive_rate.powf(1.0 / k)).ln() / k).floor() as u64;
+
+        BloomParams { expected_items, n_bits, n_hashes }
+    }
+
+    pub fn expected_items(&self) -> u64 {
+        self.expected_items
+    }
+
+    pub fn n_bits(&self) -> usize {
+        self.n_bits
+    }
+
+    pub fn n_hashes(&self) -> u16 {
+        self.n_hashes
+    }
+
+    /// False positive rate once `expected_items` distinct items have been put.
+    pub fn false_positive_rate(&self) -> f64 {
+        Self::false_positive_rate_for(self.n_bits, self.n_hashes, self.expected_items)
+    }
+
+    /// Size of the bit array in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        self.n_bits.div_ceil(8)
+    }
+
+    pub(crate) fn false_positive_rate_for(n_bits: usize, n_hashes: u16, items: u64) -> f64 {
+        let k = n_hashes as f64;
+        (1.0 - (-k * items as f64 / n_bits as f64).exp()).powf(k)
+    }
+
+    fn optimal_hashes(expected_items: u64, n_bits: usize) -> u16 {
+        let k = (n_bits as f64 / expected_items.max(1) as f64 * LN_2).round();
+        k.clamp(1.0, MAX_HASHES as f64) as u16
+    }
+
+    fn hashes_for_rate(false_positive_rate: f64) -> u16 {
+        (-false_positive_rate.log2()).round().clamp(1.0, MAX_HASHES as f64) as u16
+    }
+
+    fn check_rate(false_positive_rate: f64) {
+        assert!(false_positive_rate > 0.0 && false_positive_rate < 1.0,
+                "false positive rate must be in (0, 1), got {}", false_positive_rate);
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_params_for_items_and_fpr() {
+        let params = BloomParams::for_items_and_fpr(1_000_000, 0.01);
+        assert_eq!(params.n_bits(), 9_592_955);
+        assert_eq!(params.n_hashes(), 7);
+        assert_eq!(params.memory_bytes(), 1_199_120);
+        assert!(params.false_positive_rate() <= 0.01);
+    }
+
+    #[test]
+    fn test_params_from_any_two() {
+        let params = BloomParams::for_items_and_bits(1000, 9600);
+        assert_eq!(params.n_hashes(), 7);
+        assert!(params.false_positive_rate() < 0.01);
+
+        let params = BloomParams::for_bits_and_fpr(9_592_955, 0.01);
+        assert_eq!(params.expected_items(), 1_000_000);
+        assert_eq!(params.n_hashes(), 7);
+    }
+
+    #[test]
+    fn test_params_cap_hashes() {
+        assert_eq!(BloomParams::for_items_and_bits(0, 9600).n_hashes(), MAX_HASHES);
+        assert_eq!(BloomParams::for_items_and_bits(1, 9600).n_hashes(), MAX_HASHES);
+
+        let params = BloomParams::for_items_and_fpr(1000, 1e-30);
+        assert_eq!(params.n_hashes(), MAX_HASHES);
+        assert!(params.false_positive_rate() <= 1e-30);
+    }
+}
@@ -0,0 +1,48 @@
+//! Construction shared by the static filters: each key hash owns three slots, and the
+//! fingerprints are solved so that a key's three slots xor to its fingerprint.
+
+use crate::fingerprint::Fingerprint;
+
+
+/// Repeatedly removes keys that are alone in one of their slots. Returns each hash with the
+/// slot it owns, in peeling order, or `None` if the key graph has a core that cannot be peeled.
+pub fn peel<S: Fn(u64) -> [usize; 3]>(hashes: &[u64], n_slots: usize, slots: S) -> Option<Vec<(u64, usize)>> {
+    let mut xor_masks = vec![0u64; n_slots];
+    let mut counts = vec![0u32; n_slots];
+    for &hash in hashes {
+        for &slot in slots(hash).iter() {
+            xor_masks[slot] ^= hash;
+            counts[slot] += 1;
+        }
+    }
+
+    let mut queue: Vec<usize> = (0..n_slots).filter(|&slot| counts[slot] == 1).collect();
+    let mut order = Vec::with_capacity(hashes.len());
+    while let Some(slot) = queue.pop() {
+        if counts[slot] != 1 {
+            continue;
+        }
+        let hash = xor_masks[slot];
+        order.push((hash, slot));
+        for &other in slots(hash).iter() {
+            xor_masks[other] ^= hash;
+            counts[other] -= 1;
+            if counts[other] == 1 {
+                queue.push(other);
+            }
+        }
+    }
+
+    if order.len() == hashes.len() { Some(order) } else { None }
+}
+
+/// Assigns fingerprints in reverse peeling order, so each key's owned slot is written after
+/// its other two slots are final.
+pub fn assign<F: Fingerprint, S: Fn(u64) -> [usize; 3]>(order: Vec<(u64, usize)>, n_slots: usize, slots: S) -> Vec<F> {
+    let mut fingerprints = vec![F::default(); n_slots];
+    for (hash, slot) in order.into_iter().rev() {
+        let [h0, h1, h2] = slots(hash);
+        fingerprints[slot] = F::from_hash(hash) ^ fingerprints[h0] ^ fingerprints[h1] ^ fingerprints[h2];
+    }
+    fingerprints
+}
@@ -0,0 +1,658 @@
+use crate::error::{Error, Result};
+use crate::hash;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+
+pub const MAX_LOAD_FACTOR: f64 = 0.95;
+/// Remainders share a 64-bit slot with the three metadata bits.
+pub const MAX_REMAINDER_BITS: u32 = 64 - METADATA_BITS;
+const OCCUPIED: u64 = 0b001;
+const CONTINUATION: u64 = 0b010;
+const SHIFTED: u64 = 0b100;
+const METADATA_BITS: u32 = 3;
+
+/// Quotient filter (Bender et al., 2012). A `quotient_bits + remainder_bits` fingerprint is
+/// split into a quotient, naming the item's canonical slot, and a remainder stored in the
+/// table. Remainders with the same quotient form a sorted run; runs are shifted right past
+/// occupied slots, tracked by three metadata bits per slot:
+///
+/// * `is_occupied`: some stored fingerprint has this slot as its quotient,
+/// * `is_continuation`: the slot continues the run of the previous slot,
+/// * `is_shifted`: the remainder is not in its canonical slot.
+///
+/// Since the fingerprints can be rebuilt from the table, the filter can be resized and
+/// merged without the original items. Fingerprints are kept as a multiset: an item put
+/// twice occupies two slots and must be removed twice.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "QuotientFilterFields"))]
+pub struct QuotientFilter {
+    quotient_bits: u32,
+    remainder_bits: u32,
+    seed: u64,
+    len: usize,
+    slots: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct QuotientFilterFields {
+    quotient_bits: u32,
+    remainder_bits: u32,
+    seed: u64,
+    len: usize,
+    slots: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<QuotientFilterFields> for QuotientFilter {
+    type Error = Error;
+
+    fn try_from(fields: QuotientFilterFields) -> Result<Self> {
+        let QuotientFilterFields { quotient_bits, remainder_bits, seed, len, slots } = fields;
+        if !(1..=32).contains(&quotient_bits) || !(1..=MAX_REMAINDER_BITS).contains(&remainder_bits)
+            || quotient_bits + remainder_bits > 64 {
+            return Err(Error::Corrupt("quotient or remainder bits out of range"));
+        }
+        if slots.len() != 1 << quotient_bits {
+            return Err(Error::Corrupt("slot count does not match the quotient bits"));
+        }
+        if len >= slots.len() {
+            return Err(Error::Corrupt("a quotient filter needs an empty slot"));
+        }
+        Ok(QuotientFilter { quotient_bits, remainder_bits, seed, len, slots })
+    }
+}
+
+impl QuotientFilter {
+    /// Sizes the table for `expected_item_count` at 75% load, with `ceil(-log2(p))`
+    /// remainder bits, at most `MAX_REMAINDER_BITS` and at most what keeps fingerprints within
+    /// 64 bits. Panics if that takes more than 2^32 slots.
+    pub fn new(false_positive_rate: f64, expected_item_count: u64) -> Self {
+        let quotient_bits = (expected_item_count as f64 / 0.75).log2().ceil().max(1.0) as u32;
+        assert!(quotient_bits <= 32, "a quotient filter holds at most 2^32 slots, too few for expected_item_count");
+        let remainder_bits = (-false_positive_rate.log2()).ceil()
+            .clamp(1.0, MAX_REMAINDER_BITS.min(64 - quotient_bits) as f64) as u32;
+        QuotientFilter::with_options(rand::random(), quotient_bits, remainder_bits)
+    }
+
+    pub fn with_options(seed: u64, quotient_bits: u32, remainder_bits: u32) -> Self {
+        assert!((1..=32).contains(&quotient_bits), "quotient bits must be in 1..=32");
+        assert!((1..=MAX_REMAINDER_BITS).contains(&remainder_bits) && quotient_bits + remainder_bits <= 64,
+                "remainder bits must be in 1..=61 and fingerprints at most 64 bits");
+
+        QuotientFilter { quotient_bits, remainder_bits, seed, len: 0, slots: vec![0; 1 << quotient_bits] }
+    }
+
+    pub fn put<T: Hash>(&mut self, value: T) -> Result<()> {
+        let fingerprint = self.fingerprint(&value);
+        self.insert_fingerprint(fingerprint)
+    }
+
+    pub fn contains<T: Hash>(&self, value: T) -> bool {
+        let (quotient, remainder) = self.split(self.fingerprint(&value));
+        if !self.is_occupied(quotient) {
+            return false;
+        }
+
+        let mut slot = self.run_start(quotient);
+        loop {
+            if self.slots[slot] >> METADATA_BITS == remainder {
+                return true;
+            }
+            slot = self.next(slot);
+            if self.slots[slot] & CONTINUATION == 0 {
+                return false;
+            }
+        }
+    }
+
+    /// Removes one copy of `value`'s fingerprint. Only remove items that were put: removing
+    /// a false positive deletes the fingerprint of some other item.
+    pub fn remove<T: Hash>(&mut self, value: T) -> Result<()> {
+        let (quotient, remainder) = self.split(self.fingerprint(&value));
+        if !self.is_occupied(quotient) {
+            return Err(Error::NotPresent);
+        }
+
+        self.edit_run(quotient, |run| {
+            let index = run.iter().position(|&other| other == remainder).ok_or(Error::NotPresent)?;
+            run.remove(index);
+            Ok(())
+        })
+    }
+
+    /// Doubles the number of slots by moving one bit of every fingerprint from the remainder
+    /// to the quotient. The false positive rate stays the same once the filter is refilled
+    /// to the same load.
+    ///
+    /// Works in place: the fingerprints are decoded into the new upper half, then placed from
+    /// there into the doubled table. Fails before changing anything if no bit can be moved.
+    pub fn double(&mut self) -> Result<()> {
+        if self.remainder_bits <= 1 || self.quotient_bits >= 32 {
+            return Err(Error::Full);
+        }
+
+        let n_slots = self.n_slots();
+        let remainder_bits = self.remainder_bits;
+        // Decoding starts after an empty slot, so no cluster is entered halfway. Positions are
+        // counted from there, which keeps old quotients below `n_slots` and new ones below
+        // twice that, without wrapping.
+        let empty = (0..n_slots).find(|&slot| self.is_empty_slot(slot)).unwrap();
+        let base = (empty + 1) % n_slots;
+        self.slots.resize(2 * n_slots, 0);
+
+        let mut quotient = 0;
+        let mut stored = 0;
+        for i in 0..n_slots {
+            let slot = self.slots[(base + i) % n_slots];
+            if slot & (OCCUPIED | CONTINUATION | SHIFTED) == 0 {
+                continue;
+            }
+            if slot & SHIFTED == 0 {
+                quotient = i;
+            } else if slot & CONTINUATION == 0 {
+                quotient += 1;
+                while self.slots[(base + quotient) % n_slots] & OCCUPIED == 0 {
+                    quotient += 1;
+                }
+            }
+            self.slots[n_slots + stored] = ((quotient as u64) << remainder_bits) | (slot >> METADATA_BITS);
+            stored += 1;
+        }
+        debug_assert_eq!(stored, self.len);
+
+        // Moves the decoded fingerprints to the top `len` positions counted from `2 * base`.
+        // The `j`-th fingerprint then lands at or before its own buffer position, so the
+        // placement below never overwrites a fingerprint it has yet to read.
+        self.slots[..n_slots].fill(0);
+        let size = 2 * n_slots;
+        self.slots.rotate_right((2 * base + n_slots - self.len) % size);
+        let slot_at = |position: usize| (2 * base + position) % size;
+
+        let mut position = 0;
+        let mut previous_quotient = None;
+        for j in 0..self.len {
+            let buffered = slot_at(size - self.len + j);
+            let fingerprint = std::mem::replace(&mut self.slots[buffered], 0);
+            let quotient = (fingerprint >> (remainder_bits - 1)) as usize;
+            let remainder = fingerprint & low_bits(remainder_bits - 1);
+
+            let continuation = previous_quotient == Some(quotient);
+            if !continuation {
+                position = position.max(quotient);
+                self.slots[slot_at(quotient)] |= OCCUPIED;
+            }
+            let mut metadata = self.slots[slot_at(position)] & OCCUPIED;
+            if continuation {
+                metadata |= CONTINUATION;
+            }
+            if position != quotient {
+                metadata |= SHIFTED;
+            }
+            self.slots[slot_at(position)] = (remainder << METADATA_BITS) | metadata;
+
+            previous_quotient = Some(quotient);
+            position += 1;
+        }
+
+        self.quotient_bits += 1;
+        self.remainder_bits -= 1;
+        Ok(())
+    }
+
+    /// Adds every fingerprint of `other`, doubling this filter as often as needed. Both
+    /// filters must share the seed and fingerprint length, but may differ in size.
+    pub fn merge(&mut self, other: &QuotientFilter) -> Result<()> {
+        if self.seed != other.seed {
+            return Err(Error::Incompatible("seeds differ"));
+        }
+        if self.fingerprint_bits() != other.fingerprint_bits() {
+            return Err(Error::Incompatible("fingerprint lengths differ"));
+        }
+
+        while (self.len + other.len + 1) as f64 > MAX_LOAD_FACTOR * self.n_slots() as f64 {
+            self.double()?;
+        }
+        other.fingerprints().into_iter().try_for_each(|fingerprint| self.insert_fingerprint(fingerprint))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn n_slots(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn quotient_bits(&self) -> u32 {
+        self.quotient_bits
+    }
+
+    pub fn remainder_bits(&self) -> u32 {
+        self.remainder_bits
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.n_slots() as f64
+    }
+
+    /// Expected false positive rate at the current load: `1 - e^(-load / 2^remainder_bits)`.
+    pub fn false_positive_rate(&self) -> f64 {
+        1.0 - (-self.load_factor() / (self.remainder_bits as f64).exp2()).exp()
+    }
+
+    fn fingerprint_bits(&self) -> u32 {
+        self.quotient_bits + self.remainder_bits
+    }
+
+    pub(crate) fn fingerprint<T: Hash>(&self, value: &T) -> u64 {
+        hash::hash128(value, self.seed).0 & low_bits(self.fingerprint_bits())
+    }
+
+    pub(crate) fn split(&self, fingerprint: u64) -> (usize, u64) {
+        ((fingerprint >> self.remainder_bits) as usize, fingerprint & low_bits(self.remainder_bits))
+    }
+
+    fn has_room(&self, extra: usize) -> bool {
+        (self.len + extra) as f64 <= MAX_LOAD_FACTOR * self.n_slots() as f64
+    }
+
+    pub(crate) fn is_occupied(&self, quotient: usize) -> bool {
+        self.slots[quotient] & OCCUPIED != 0
+    }
+
+    fn insert_fingerprint(&mut self, fingerprint: u64) -> Result<()> {
+        let (quotient, remainder) = self.split(fingerprint);
+        self.edit_run(quotient, |run| {
+            let index = run.iter().position(|&other| other > remainder).unwrap_or(run.len());
+            run.insert(index, remainder);
+            Ok(())
+        })
+    }
+
+    /// First slot of the run of `quotient`, which must be occupied: counts runs from the start
+    /// of the cluster, the first unshifted slot at or before `quotient`.
+    fn run_start(&self, quotient: usize) -> usize {
+        let mut canonical = quotient;
+        while self.slots[canonical] & SHIFTED != 0 {
+            canonical = self.prev(canonical);
+        }
+
+        let mut slot = canonical;
+        while canonical != quotient {
+            slot = self.next(slot);
+            while self.slots[slot] & CONTINUATION != 0 {
+                slot = self.next(slot);
+            }
+            canonical = self.next(canonical);
+            while self.slots[canonical] & OCCUPIED == 0 {
+                canonical = self.next(canonical);
+            }
+        }
+        slot
+    }
+
+    /// Every stored fingerprint, in slot order.
+    fn fingerprints(&self) -> Vec<u64> {
+        let mut fingerprints = Vec::with_capacity(self.len);
+        if self.len == 0 {
+            return fingerprints;
+        }
+
+        // Start right after an empty slot so that no region is entered halfway.
+        let empty = (0..self.n_slots()).find(|&slot| self.is_empty_slot(slot)).unwrap();
+        let mut slot = self.next(empty);
+        let mut visited = 1;
+        while visited < self.n_slots() {
+            if self.is_empty_slot(slot) {
+                slot = self.next(slot);
+                visited += 1;
+                continue;
+            }
+            let (_, elements) = self.region(slot);
+            visited += elements.len();
+            slot = (slot + elements.len()) & (self.n_slots() - 1);
+            fingerprints.extend(elements.into_iter()
+                .map(|(quotient, remainder)| ((quotient as u64) << self.remainder_bits) | remainder));
+        }
+        fingerprints
+    }
+
+    /// The maximal sequence of non-empty slots containing `slot`: its first slot and the
+    /// `(quotient, remainder)` pairs stored in it, in slot order. The first slot of such a
+    /// sequence always holds an unshifted remainder, so quotients can be recovered by pairing
+    /// runs with occupied slots from there on.
+    fn region(&self, slot: usize) -> (usize, Vec<(usize, u64)>) {
+        if self.is_empty_slot(slot) {
+            return (slot, vec![]);
+        }
+
+        let mut start = slot;
+        while !self.is_empty_slot(self.prev(start)) {
+            start = self.prev(start);
+        }
+
+        let mut elements = vec![];
+        let mut quotient = start;
+        let mut slot = start;
+        loop {
+            if !elements.is_empty() && self.slots[slot] & CONTINUATION == 0 {
+                quotient = self.next(quotient);
+                while self.slots[quotient] & OCCUPIED == 0 {
+                    quotient = self.next(quotient);
+                }
+            }
+            elements.push((quotient, self.slots[slot] >> METADATA_BITS));
+
+            slot = self.next(slot);
+            if self.is_empty_slot(slot) {
+                return (start, elements);
+            }
+        }
+    }
+
+    /// The remainders in the run of `quotient`, in slot order; empty if it is not occupied.
+    pub(crate) fn run(&self, quotient: usize) -> Vec<u64> {
+        let mut run = vec![];
+        if !self.is_occupied(quotient) {
+            return run;
+        }
+
+        let mut slot = self.run_start(quotient);
+        loop {
+            run.push(self.slots[slot] >> METADATA_BITS);
+            slot = self.next(slot);
+            if self.slots[slot] & CONTINUATION == 0 {
+                return run;
+            }
+        }
+    }
+
+    /// Lets `edit` change the remainders of the run of `quotient`, given in slot order, and
+    /// stores the result in place: a run that grows shifts the slots after it forward up to
+    /// the next empty slot, one that shrinks moves them back as far as their quotients allow.
+    /// Nothing changes if `edit` fails or the result does not fit.
+    pub(crate) fn edit_run<E>(&mut self, quotient: usize, edit: E) -> Result<()>
+        where E: FnOnce(&mut Vec<u64>) -> Result<()> {
+        let mut run = self.run(quotient);
+        let old_len = run.len();
+        edit(&mut run)?;
+        if run.len() > old_len && !self.has_room(run.len() - old_len) {
+            return Err(Error::Full);
+        }
+
+        // A new run starts where `run_start` would find it once `quotient` is occupied.
+        let was_occupied = self.is_occupied(quotient);
+        self.slots[quotient] |= OCCUPIED;
+        let start = self.run_start(quotient);
+        if !was_occupied {
+            self.slots[quotient] &= !OCCUPIED;
+        }
+
+        let mask = self.n_slots() - 1;
+        for i in old_len..run.len() {
+            self.shift_forward((start + i) & mask);
+        }
+        for (i, &remainder) in run.iter().enumerate() {
+            let slot = (start + i) & mask;
+            let mut metadata = self.slots[slot] & OCCUPIED;
+            if i > 0 {
+                metadata |= CONTINUATION;
+            }
+            if slot != quotient {
+                metadata |= SHIFTED;
+            }
+            self.slots[slot] = (remainder << METADATA_BITS) | metadata;
+        }
+        if run.is_empty() {
+            self.slots[quotient] &= !OCCUPIED;
+        } else {
+            self.slots[quotient] |= OCCUPIED;
+        }
+        for _ in run.len()..old_len {
+            self.shift_back((start + run.len()) & mask, quotient);
+        }
+
+        self.len = self.len + run.len() - old_len;
+        Ok(())
+    }
+
+    /// Frees `slot` by moving it and the slots after it, up to the next empty one, forward
+    /// by one. Occupied bits stay with their slots.
+    fn shift_forward(&mut self, slot: usize) {
+        let mut end = slot;
+        while !self.is_empty_slot(end) {
+            end = self.next(end);
+        }
+        while end != slot {
+            let previous = self.prev(end);
+            self.slots[end] = (self.slots[end] & OCCUPIED) | (self.slots[previous] & !OCCUPIED) | SHIFTED;
+            end = previous;
+        }
+        self.slots[slot] &= OCCUPIED;
+    }
+
+    /// Drops the remainder in `hole`, which is in or right after the run of `quotient`, and
+    /// moves the slots after it back by one up to the first empty or unshifted slot.
+    fn shift_back(&mut self, mut hole: usize, mut quotient: usize) {
+        loop {
+            let next = self.next(hole);
+            if self.slots[next] & SHIFTED == 0 {
+                self.slots[hole] &= OCCUPIED;
+                return;
+            }
+            if self.slots[next] & CONTINUATION == 0 {
+                quotient = self.next(quotient);
+                while self.slots[quotient] & OCCUPIED == 0 {
+                    quotient = self.next(quotient);
+                }
+            }
+            let shifted = if hole == quotient { 0 } else { SHIFTED };
+            self.slots[hole] = (self.slots[hole] & OCCUPIED) | (self.slots[next] & !(OCCUPIED | SHIFTED)) | shifted;
+            hole = next;
+        }
+    }
+
+    fn is_empty_slot(&self, slot: usize) -> bool {
+        self.slots[slot] & (OCCUPIED | CONTINUATION | SHIFTED) == 0
+    }
+
+    fn next(&self, slot: usize) -> usize {
+        (slot + 1) & (self.n_slots() - 1)
+    }
+
+    fn prev(&self, slot: usize) -> usize {
+        (slot + self.n_slots() - 1) & (self.n_slots() - 1)
+    }
+}
+
+fn low_bits(bits: u32) -> u64 {
+    if bits >= 64 { u64::MAX } else { (1 << bits) - 1 }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_quotient_filter_put_contains_remove() {
+        let mut filter = QuotientFilter::new(0.01, 10000);
+        (0..10000u64).for_each(|n| filter.put(n).unwrap());
+        assert_eq!(filter.len(), 10000);
+        assert!((0..10000u64).all(|n| filter.contains(n)));
+
+        let false_positives = (10000..20000u64).filter(|&n| filter.contains(n)).count();
+        assert!(false_positives < 100, "{}", false_positives);
+
+        (0..5000u64).for_each(|n| filter.remove(n).unwrap());
+        assert_eq!(filter.len(), 5000);
+        assert!((5000..10000u64).all(|n| filter.contains(n)));
+        assert_eq!(filter.fingerprints().len(), 5000);
+    }
+
+    #[test]
+    fn test_quotient_filter_wraps_around_and_fills() {
+        let mut filter = QuotientFilter::with_options(3, 4, 8);
+        let mut inserted = vec![];
+        let mut n = 0u64;
+        while filter.put(n).is_ok() {
+            inserted.push(n);
+            n += 1;
+        }
+
+        assert_eq!(filter.len(), 15);
+        assert!(inserted.iter().all(|n| filter.contains(n)));
+        inserted.iter().for_each(|n| filter.remove(n).unwrap());
+        assert!(filter.is_empty());
+        assert!(filter.slots.iter().all(|&slot| slot == 0));
+    }
+
+    #[test]
+    fn test_quotient_filter_keeps_duplicates() {
+        let mut filter = QuotientFilter::with_options(1, 6, 10);
+        filter.put("twice").unwrap();
+        filter.put("twice").unwrap();
+
+        filter.remove("twice").unwrap();
+        assert!(filter.contains("twice"));
+        filter.remove("twice").unwrap();
+        assert!(!filter.contains("twice"));
+        assert!(matches!(filter.remove("twice"), Err(Error::NotPresent)));
+    }
+
+    #[test]
+    fn test_quotient_filter_double() {
+        let mut filter = QuotientFilter::with_options(5, 8, 12);
+        (0..200u64).for_each(|n| filter.put(n).unwrap());
+
+        filter.double().unwrap();
+        assert_eq!(filter.n_slots(), 512);
+        assert_eq!(filter.remainder_bits(), 11);
+        assert_eq!(filter.len(), 200);
+        assert!((0..200u64).all(|n| filter.contains(n)));
+        (200..400u64).for_each(|n| filter.put(n).unwrap());
+        assert!((0..400u64).all(|n| filter.contains(n)));
+
+        let mut full = QuotientFilter::with_options(5, 4, 1);
+        full.put(1u64).unwrap();
+        assert!(matches!(full.double(), Err(Error::Full)));
+        assert_eq!((full.n_slots(), full.remainder_bits(), full.len()), (16, 1, 1));
+        assert!(full.contains(1u64));
+    }
+
+    #[test]
+    fn test_quotient_filter_double_matches_rebuild() {
+        // Nearly full tables, so clusters wrap around the end.
+        for seed in 0..20 {
+            let mut filter = QuotientFilter::with_options(seed, 6, 10);
+            let mut n = 0u64;
+            while filter.load_factor() < 0.9 {
+                filter.put(n).unwrap();
+                n += 1;
+            }
+            filter.double().unwrap();
+
+            let mut rebuilt = QuotientFilter::with_options(seed, 7, 9);
+            (0..n).for_each(|n| rebuilt.put(n).unwrap());
+            assert!(filter.slots == rebuilt.slots, "seed {}", seed);
+            assert_eq!(filter.len(), rebuilt.len());
+        }
+    }
+
+    #[test]
+    fn test_quotient_filter_wide_remainders() {
+        let mut filter = QuotientFilter::with_options(1, 1, MAX_REMAINDER_BITS);
+        filter.put(1u64).unwrap();
+        assert!(filter.contains(1u64));
+        assert_eq!(QuotientFilter::new(1e-10, 100).remainder_bits(), 34);
+        assert_eq!(QuotientFilter::new(f64::MIN_POSITIVE, 1).remainder_bits(), MAX_REMAINDER_BITS);
+        assert_eq!(QuotientFilter::new(f64::MIN_POSITIVE, 100).remainder_bits(), 56);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_quotient_filter_serde_checks_slots() {
+        let mut filter = QuotientFilter::with_options(1, 6, 10);
+        (0..40u64).for_each(|n| filter.put(n).unwrap());
+        let json = serde_json::to_value(&filter).unwrap();
+        let restored: QuotientFilter = serde_json::from_value(json.clone()).unwrap();
+        assert!((0..40u64).all(|n| restored.contains(n)));
+
+        let mut short_table = json.clone();
+        short_table["slots"].as_array_mut().unwrap().pop();
+        assert!(serde_json::from_value::<QuotientFilter>(short_table).is_err());
+        let mut wide_remainders = json;
+        wide_remainders["remainder_bits"] = 62.into();
+        assert!(serde_json::from_value::<QuotientFilter>(wide_remainders).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "remainder bits")]
+    fn test_quotient_filter_rejects_remainders_over_61_bits() {
+        QuotientFilter::with_options(1, 1, 63);
+    }
+
+    #[test]
+    #[should_panic(expected = "at most 2^32 slots")]
+    fn test_quotient_filter_rejects_more_than_2_32_slots() {
+        QuotientFilter::new(0.01, u64::MAX);
+    }
+
+    #[test]
+    fn test_quotient_filter_shifts_in_place() {
+        // Crowded quotients in a small table make long clusters that wrap around the end.
+        let mut filter = QuotientFilter::with_options(9, 6, 4);
+        let mut stored: Vec<u64> = vec![];
+        let mut rng = rand::thread_rng();
+        for _ in 0..5000 {
+            let quotient: u64 = (58 + rng.gen_range(0, 12)) % 64;
+            let fingerprint = quotient << 4 | rng.gen_range(0, 4);
+            if rng.gen_range(0, 3) > 0 && filter.insert_fingerprint(fingerprint).is_ok() {
+                stored.push(fingerprint);
+            } else if let Some(index) = stored.iter().position(|&other| other == fingerprint) {
+                stored.swap_remove(index);
+                let (quotient, remainder) = filter.split(fingerprint);
+                filter.edit_run(quotient, |run| {
+                    run.remove(run.iter().position(|&other| other == remainder).unwrap());
+                    Ok(())
+                }).unwrap();
+            }
+
+            let mut fingerprints = filter.fingerprints();
+            fingerprints.sort_unstable();
+            stored.sort_unstable();
+            assert_eq!(fingerprints, stored);
+        }
+    }
+
+    #[test]
+    fn test_quotient_filter_merge() {
+        let mut left = QuotientFilter::with_options(7, 8, 12);
+        let mut right = QuotientFilter::with_options(7, 9, 11);
+        (0..200u64).for_each(|n| left.put(n).unwrap());
+        (200..600u64).for_each(|n| right.put(n).unwrap());
+
+        left.merge(&right).unwrap();
+        assert_eq!(left.len(), 600);
+        assert!((0..600u64).all(|n| left.contains(n)));
+
+        let other_seed = QuotientFilter::with_options(8, 8, 12);
+        assert!(matches!(left.merge(&other_seed), Err(Error::Incompatible(_))));
+        let other_length = QuotientFilter::with_options(7, 8, 13);
+        assert!(matches!(left.merge(&other_length), Err(Error::Incompatible(_))));
+    }
+}
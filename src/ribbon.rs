@@ -0,0 +1,231 @@
+use crate::error::{Error, Result};
+use crate::fingerprint;
+use crate::hash;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+
+const MAX_ATTEMPTS: usize = 100;
+/// Width of the coefficient band: every key's equation touches 64 consecutive slots.
+const RIBBON_WIDTH: usize = 64;
+
+/// Standard Ribbon filter (Dillinger & Walzer, 2021). Every key gets a random 64-bit
+/// coefficient row starting at a random slot and an `r`-bit result; construction solves the
+/// resulting banded linear system over GF(2) by on-the-fly Gaussian elimination, and a query
+/// checks that the solution rows selected by the key's coefficients xor to its result.
+///
+/// Takes about `1.07 * r` bits per key for a million keys and a false positive rate of
+/// `2^-r`, growing slowly with the key count (about `1.2 * r` at a hundred million), against
+/// `1.44 * r` for `BloomFilter`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RibbonFilterFields"))]
+pub struct RibbonFilter {
+    seed: u64,
+    mix_seed: u64,
+    result_bits: u32,
+    n_slots: usize,
+    len: usize,
+    /// The solution stored column-major: `result_bits` bit vectors of `n_slots` bits each,
+    /// so a query reads one 64-bit window per result bit.
+    solution: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RibbonFilterFields {
+    seed: u64,
+    mix_seed: u64,
+    result_bits: u32,
+    n_slots: usize,
+    len: usize,
+    solution: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RibbonFilterFields> for RibbonFilter {
+    type Error = Error;
+
+    fn try_from(fields: RibbonFilterFields) -> Result<Self> {
+        let RibbonFilterFields { seed, mix_seed, result_bits, n_slots, len, solution } = fields;
+        if !(1..=32).contains(&result_bits) || n_slots < RIBBON_WIDTH {
+            return Err(Error::Corrupt("ribbon filter dimensions out of range"));
+        }
+        if solution.len() != n_slots.div_ceil(64) * result_bits as usize {
+            return Err(Error::Corrupt("solution size does not match the slot count"));
+        }
+        Ok(RibbonFilter { seed, mix_seed, result_bits, n_slots, len, solution })
+    }
+}
+
+impl RibbonFilter {
+    pub fn build<I>(result_bits: u32, keys: I) -> Result<Self> where I: IntoIterator, I::Item: Hash {
+        RibbonFilter::build_with_seed(rand::random(), result_bits, keys)
+    }
+
+    /// Builds the filter with `result_bits` in `1..=32`, retrying with a fresh mixing seed and
+    /// 1% more slots whenever the system has no solution. Duplicate keys are ignored.
+    pub fn build_with_seed<I>(seed: u64, result_bits: u32, keys: I) -> Result<Self>
+        where I: IntoIterator, I::Item: Hash {
+        assert!((1..=32).contains(&result_bits), "result bits must be in 1..=32");
+
+        let mut hashes: Vec<u64> = keys.into_iter().map(|key| hash::hash128(&key, seed).0).collect();
+        hashes.sort_unstable();
+        hashes.dedup();
+
+        let mut overhead = Self::space_overhead(hashes.len());
+        let mut mix_seed = seed;
+
+        for _ in 0..MAX_ATTEMPTS {
+            let n_slots = (hashes.len() as f64 * overhead).ceil() as usize + RIBBON_WIDTH;
+            overhead += 0.01;
+            mix_seed = fingerprint::mix64(mix_seed.wrapping_add(0x9e37_79b9_7f4a_7c15));
+            let mut filter = RibbonFilter { seed, mix_seed, result_bits, n_slots, len: hashes.len(), solution: vec![] };
+            if let Some((coefficients, results)) = filter.band(&hashes) {
+                filter.back_substitute(&coefficients, &results);
+                return Ok(filter);
+            }
+        }
+        Err(Error::BuildFailed("banded system has no solution"))
+    }
+
+    pub fn contains<T: Hash>(&self, value: T) -> bool {
+        let (start, coefficients, result) = self.equation(hash::hash128(&value, self.seed).0);
+        (0..self.result_bits).all(|bit| {
+            let parity = (self.window(bit, start) & coefficients).count_ones() & 1;
+            parity == (result >> bit) & 1
+        })
+    }
+
+    /// Number of distinct keys the filter was built from.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn result_bits(&self) -> u32 {
+        self.result_bits
+    }
+
+    pub fn bits_per_key(&self) -> f64 {
+        (self.n_slots * self.result_bits as usize) as f64 / self.len.max(1) as f64
+    }
+
+    /// `2^-result_bits`.
+    pub fn false_positive_rate(&self) -> f64 {
+        (-(self.result_bits as f64)).exp2()
+    }
+
+    /// Slots per key at which the first attempt succeeds about half the time: a fit of
+    /// measured success rates for the 64-bit band, whose needed overhead grows with `log(n)`.
+    fn space_overhead(n_keys: usize) -> f64 {
+        1.07 + 0.017 * (n_keys as f64 / 1e6).log2().max(0.0)
+    }
+
+    /// The key's first slot, its 64 coefficients (bit `j` for slot `start + j`, bit 0
+    /// always set) and its expected result.
+    fn equation(&self, key_hash: u64) -> (usize, u64, u32) {
+        let hash = fingerprint::mix64(key_hash.wrapping_add(self.mix_seed));
+        let start = ((hash as u128 * (self.n_slots - RIBBON_WIDTH + 1) as u128) >> 64) as usize;
+        let coefficients = fingerprint::mix64(hash ^ 0x2545_f491_4f6c_dd1d) | 1;
+        let result = (fingerprint::mix64(hash ^ 0x6a09_e667_f3bc_c909) & ((1u64 << self.result_bits) - 1)) as u32;
+        (start, coefficients, result)
+    }
+
+    /// Gaussian elimination keeping each slot's row with its lowest coefficient at that
+    /// slot. Fails when a key's row reduces to `0 = 1`.
+    fn band(&self, key_hashes: &[u64]) -> Option<(Vec<u64>, Vec<u32>)> {
+        let mut coefficients = vec![0u64; self.n_slots];
+        let mut results = vec![0u32; self.n_slots];
+
+        for &key_hash in key_hashes {
+            let (mut slot, mut row, mut result) = self.equation(key_hash);
+            loop {
+                if coefficients[slot] == 0 {
+                    coefficients[slot] = row;
+                    results[slot] = result;
+                    break;
+                }
+                row ^= coefficients[slot];
+                result ^= results[slot];
+                if row == 0 {
+                    if result != 0 {
+                        return None;
+                    }
+                    break;
+                }
+                let shift = row.trailing_zeros();
+                slot += shift as usize;
+                row >>= shift;
+            }
+        }
+        Some((coefficients, results))
+    }
+
+    fn back_substitute(&mut self, coefficients: &[u64], results: &[u32]) {
+        let words = self.words_per_column();
+        self.solution = vec![0; words * self.result_bits as usize];
+
+        for slot in (0..self.n_slots).rev() {
+            for bit in 0..self.result_bits {
+                // The slot's own bit is still zero, so the window only covers later slots.
+                let parity = (self.window(bit, slot) & coefficients[slot]).count_ones() & 1;
+                if parity ^ ((results[slot] >> bit) & 1) == 1 {
+                    self.solution[bit as usize * words + slot / 64] |= 1 << (slot % 64);
+                }
+            }
+        }
+    }
+
+    /// The 64 solution bits of column `bit` starting at `slot`, lowest slot first.
+    fn window(&self, bit: u32, slot: usize) -> u64 {
+        let column = &self.solution[bit as usize * self.words_per_column()..][..self.words_per_column()];
+        let (word, shift) = (slot / 64, slot % 64);
+        let low = column[word] >> shift;
+        match column.get(word + 1) {
+            Some(&high) if shift > 0 => low | (high << (64 - shift)),
+            _ => low,
+        }
+    }
+
+    fn words_per_column(&self) -> usize {
+        self.n_slots.div_ceil(64)
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ribbon_filter() {
+        let keys: Vec<u64> = (0..100000).collect();
+        let filter = RibbonFilter::build(8, &keys).unwrap();
+
+        assert_eq!(filter.len(), 100000);
+        assert!(filter.bits_per_key() < 9.0, "{}", filter.bits_per_key());
+        assert!(keys.iter().all(|key| filter.contains(key)));
+        let false_positives = (100000..200000u64).filter(|n| filter.contains(n)).count();
+        assert!(false_positives < 500, "{}", false_positives);
+    }
+
+    #[test]
+    fn test_ribbon_filter_result_bits() {
+        let keys = (0..20000u64).chain(0..20000u64);
+        let filter = RibbonFilter::build_with_seed(3, 16, keys).unwrap();
+
+        assert_eq!(filter.len(), 20000);
+        assert!((0..20000u64).all(|key| filter.contains(key)));
+        assert!((20000..120000u64).filter(|n| filter.contains(n)).count() < 10);
+
+        let filter = RibbonFilter::build(1, 0..1000u64).unwrap();
+        assert!((0..1000u64).all(|key| filter.contains(key)));
+        assert!(RibbonFilter::build(4, Vec::<u64>::new()).unwrap().is_empty());
+    }
+}
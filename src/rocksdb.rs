@@ -0,0 +1,268 @@
+use crate::error::{Error, Result};
+use crate::hash;
+use crate::membership::MembershipQuery;
+
+
+/// Bytes per cache-line block.
+pub const BLOCK_BYTES: usize = 64;
+/// Length of the trailer after the blocks: a `-1` marker for the newer Bloom formats, the
+/// sub-implementation (0 for FastLocalBloom), the block size and probe count, and two
+/// reserved zero bytes.
+const METADATA_LEN: usize = 5;
+const NEW_BLOOM_MARKER: u8 = 0xff;
+const FAST_LOCAL_BLOOM: u8 = 0;
+/// Golden-ratio multiplier that steps from one probe's hash to the next.
+const PROBE_MULTIPLIER: u32 = 0x9e37_79b9;
+
+/// Full filter block in RocksDB's FastLocalBloom format, written by `NewBloomFilterPolicy` for
+/// tables with `format_version=5` and later. Keys are hashed with `hash::xxph3_64`: the lower
+/// 32 bits pick a 64-byte block and the upper 32 bits, multiplied by a golden-ratio constant
+/// between probes, give each probe's bit within it by their top nine bits.
+///
+/// A block of `METADATA_LEN` bytes or less is how RocksDB stores a filter with no keys, and
+/// it matches nothing. Metadata that RocksDB reserves for future formats makes a filter that
+/// matches everything, as RocksDB reads it, rather than an error.
+pub struct FastLocalBloomFilter {
+    data: Vec<u8>,
+    n_probes: u8,
+    always_true: bool,
+}
+
+impl FastLocalBloomFilter {
+    /// Reads the contents of a table's filter block. Legacy and Ribbon filters, which share
+    /// the trailer layout, are reported as `Error::UnsupportedHashScheme` with their marker.
+    /// Like RocksDB's `GetBuiltinFilterBitsReader`, a reserved marker, sub-implementation,
+    /// block size, probe count or seed gives a filter whose `contains` is always `true`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() <= METADATA_LEN {
+            return Ok(FastLocalBloomFilter { data: bytes.to_vec(), n_probes: 0, always_true: false });
+        }
+
+        let len = bytes.len() - METADATA_LEN;
+        let metadata = &bytes[len..];
+        match metadata[0] as i8 {
+            // Legacy Bloom filters store their probe count here, Ribbon filters -2.
+            1..=127 | -2 => return Err(Error::UnsupportedHashScheme(metadata[0])),
+            -1 => {}
+            _ => return Ok(Self::always_true(bytes)),
+        }
+        let n_probes = metadata[2] & 31;
+        if metadata[1] != FAST_LOCAL_BLOOM || metadata[2] >> 5 != 0 || n_probes == 0 || n_probes > 30
+            || metadata[3..] != [0, 0] {
+            return Ok(Self::always_true(bytes));
+        }
+        if len % BLOCK_BYTES != 0 || len / BLOCK_BYTES > u32::MAX as usize {
+            return Err(Error::Corrupt("FastLocalBloom data is not a whole number of blocks"));
+        }
+        Ok(FastLocalBloomFilter { data: bytes.to_vec(), n_probes, always_true: false })
+    }
+
+    fn always_true(bytes: &[u8]) -> Self {
+        FastLocalBloomFilter { data: bytes.to_vec(), n_probes: 0, always_true: true }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn n_probes(&self) -> u8 {
+        self.n_probes
+    }
+
+    /// Number of 64-byte blocks, 0 for a filter in a reserved format.
+    pub fn n_blocks(&self) -> usize {
+        if self.always_true {
+            return 0;
+        }
+        self.data.len().saturating_sub(METADATA_LEN) / BLOCK_BYTES
+    }
+
+    /// `true` when the metadata is reserved for a format this reader does not know, so every
+    /// key may be present.
+    pub fn is_always_true(&self) -> bool {
+        self.always_true
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.contains_hash(hash::xxph3_64(key))
+    }
+
+    /// Looks up an already computed `GetSliceHash64`.
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        if self.always_true {
+            return true;
+        }
+        if self.n_blocks() == 0 {
+            return false;
+        }
+        let block = &self.data[Self::block_offset(hash, self.n_blocks())..][..BLOCK_BYTES];
+        Self::probes(hash, self.n_probes).all(|bit| block[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    fn block_offset(hash: u64, n_blocks: usize) -> usize {
+        (((hash as u32 as u64) * n_blocks as u64) >> 32) as usize * BLOCK_BYTES
+    }
+
+    fn probes(hash: u64, n_probes: u8) -> impl Iterator<Item = usize> {
+        let start = (hash >> 32) as u32;
+        (0..n_probes).scan(start, |h, _| {
+            let bit = (*h >> 23) as usize;
+            *h = h.wrapping_mul(PROBE_MULTIPLIER);
+            Some(bit)
+        })
+    }
+}
+
+impl MembershipQuery<[u8]> for FastLocalBloomFilter {
+    fn contains(&self, key: &[u8]) -> bool {
+        FastLocalBloomFilter::contains(self, key)
+    }
+}
+
+impl MembershipQuery<str> for FastLocalBloomFilter {
+    fn contains(&self, key: &str) -> bool {
+        FastLocalBloomFilter::contains(self, key.as_bytes())
+    }
+}
+
+/// Builds filters the way RocksDB's `NewBloomFilterPolicy(bits_per_key)` does for
+/// `format_version=5`, without `optimize_filters_for_memory`: `bits_per_key` is clamped to
+/// `1..=100` and rounded to thousandths, the space is rounded up to whole blocks and the probe
+/// count comes from RocksDB's table for the requested bits per key.
+pub struct FastLocalBloomBuilder {
+    millibits_per_key: u64,
+    hashes: Vec<u64>,
+}
+
+impl FastLocalBloomBuilder {
+    pub fn new(bits_per_key: f64) -> Self {
+        let bits_per_key = if bits_per_key < 100.0 { bits_per_key.max(1.0) } else { 100.0 };
+        let millibits_per_key = (bits_per_key * 1000.0 + 0.500001) as u64;
+        FastLocalBloomBuilder { millibits_per_key, hashes: vec![] }
+    }
+
+    /// Like RocksDB, skips a key whose hash repeats the previous key's, which is common with
+    /// prefix extractors.
+    pub fn add_key(&mut self, key: &[u8]) {
+        let hash = hash::xxph3_64(key);
+        if self.hashes.last() != Some(&hash) {
+            self.hashes.push(hash);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Filter over every key added so far, byte for byte what RocksDB writes for them: with
+    /// no keys, an empty block without metadata.
+    pub fn finish(&self) -> FastLocalBloomFilter {
+        if self.hashes.is_empty() {
+            return FastLocalBloomFilter { data: vec![], n_probes: 0, always_true: false };
+        }
+        let len = (self.hashes.len() as u64 * self.millibits_per_key).div_ceil(8000).min(0xffff_ffc0) as usize;
+        let len = len.div_ceil(BLOCK_BYTES) * BLOCK_BYTES;
+        let n_probes = Self::choose_n_probes(self.millibits_per_key);
+
+        let mut data = vec![0u8; len + METADATA_LEN];
+        let n_blocks = len / BLOCK_BYTES;
+        for &hash in self.hashes.iter() {
+            let block = &mut data[FastLocalBloomFilter::block_offset(hash, n_blocks)..][..BLOCK_BYTES];
+            for bit in FastLocalBloomFilter::probes(hash, n_probes) {
+                block[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        data[len..len + 3].copy_from_slice(&[NEW_BLOOM_MARKER, FAST_LOCAL_BLOOM, n_probes]);
+        FastLocalBloomFilter { data, n_probes, always_true: false }
+    }
+
+    /// `FastLocalBloomImpl::ChooseNumProbes`, tuned for the lowest false positive rate at each
+    /// size given the cache-line locality.
+    fn choose_n_probes(millibits_per_key: u64) -> u8 {
+        const LIMITS: [u64; 12] = [2080, 3580, 5100, 6640, 8300, 10070, 11720, 14001, 16050, 18300, 22001, 25501];
+        match LIMITS.iter().position(|&limit| millibits_per_key <= limit) {
+            Some(index) => index as u8 + 1,
+            None if millibits_per_key > 50000 => 24,
+            None => ((millibits_per_key - 1) / 2000 - 1) as u8,
+        }
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // To be written by tests/fixtures/rocksdb/generate_fixture.cc through RocksDB 8.10's
+    // NewBloomFilterPolicy builder. The checked-in file predates that generator: it was
+    // written with RocksDB's hash and FastLocalBloomImpl around a copy of the builder's sizing.
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/rocksdb/fast_local_bloom.bin");
+
+    #[test]
+    fn test_fast_local_bloom_reads_fixture() {
+        let filter = FastLocalBloomFilter::from_bytes(FIXTURE).unwrap();
+        assert_eq!((filter.n_probes(), filter.n_blocks()), (6, 20));
+        assert!((0..1000).all(|n| filter.contains(format!("key-{}", n).as_bytes())));
+        // 1000 keys in 20 cache lines with 6 probes: the blocks' fill gives 0.85%, so expect 85
+        // of 10000, sigma 9.
+        let false_positives = (1000..11000).filter(|n| filter.contains(format!("key-{}", n).as_bytes())).count();
+        assert!(false_positives < 120, "{}", false_positives);
+    }
+
+    #[test]
+    fn test_fast_local_bloom_builder_matches_fixture() {
+        let mut builder = FastLocalBloomBuilder::new(10.0);
+        (0..1000).for_each(|n| builder.add_key(format!("key-{}", n).as_bytes()));
+        builder.add_key(b"key-999");
+        assert_eq!(builder.len(), 1000);
+        assert!(builder.finish().as_bytes() == FIXTURE);
+    }
+
+    #[test]
+    fn test_fast_local_bloom_probe_counts() {
+        let probes = |bits_per_key| {
+            FastLocalBloomBuilder::choose_n_probes(FastLocalBloomBuilder::new(bits_per_key).millibits_per_key)
+        };
+        assert_eq!(probes(0.1), 1);
+        assert_eq!(probes(6.64), 4);
+        assert_eq!(probes(6.641), 5);
+        assert_eq!(probes(10.0), 6);
+        assert_eq!(probes(30.0), 13);
+        assert_eq!(probes(100.0), 24);
+        assert_eq!(probes(f64::NAN), 24);
+    }
+
+    #[test]
+    fn test_fast_local_bloom_reads_other_formats_like_rocksdb() {
+        let empty = FastLocalBloomBuilder::new(10.0).finish();
+        assert!(empty.as_bytes().is_empty());
+        assert!(!empty.contains(b"anything"));
+        assert!(!FastLocalBloomFilter::from_bytes(&[0xff, 0, 6, 0, 0]).unwrap().contains(b"anything"));
+
+        let with_marker = |marker: u8, sub_impl: u8, probes: u8, seed: u8| {
+            let mut bytes = FIXTURE.to_vec();
+            let len = bytes.len();
+            bytes[len - 5..].copy_from_slice(&[marker, sub_impl, probes, seed, 0]);
+            FastLocalBloomFilter::from_bytes(&bytes)
+        };
+        assert!(!with_marker(0xff, 0, 6, 0).unwrap().is_always_true());
+        assert!(matches!(with_marker(0xfe, 0, 6, 0), Err(Error::UnsupportedHashScheme(0xfe))));
+        assert!(matches!(with_marker(6, 0, 0, 0), Err(Error::UnsupportedHashScheme(6))));
+
+        // Reserved metadata: a marker of 0 or below -2, another sub-implementation, another
+        // block size, 0 or more than 30 probes, or a seed.
+        for &(marker, sub_impl, probes, seed) in
+            [(0, 0, 6, 0), (0xfd, 0, 6, 0), (0xff, 1, 6, 0), (0xff, 0, 0x26, 0), (0xff, 0, 0, 0), (0xff, 0, 31, 0),
+             (0xff, 0, 6, 1)].iter() {
+            let filter = with_marker(marker, sub_impl, probes, seed).unwrap();
+            assert!(filter.is_always_true());
+            assert_eq!((filter.n_probes(), filter.n_blocks()), (0, 0));
+            assert!(filter.contains(b"anything") && filter.contains_hash(0));
+        }
+        assert!(matches!(FastLocalBloomFilter::from_bytes(&FIXTURE[1..]), Err(Error::Corrupt(_))));
+    }
+}
@@ -0,0 +1,180 @@
+use crate::bloom::BloomFilter;
+#[cfg(feature = "serde")]
+use crate::error::{Error, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+
+pub const DEFAULT_GROWTH_FACTOR: u64 = 2;
+pub const DEFAULT_TIGHTENING_RATIO: f64 = 0.5;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct Stage {
+    filter: BloomFilter,
+    capacity: u64,
+    len: u64,
+}
+
+/// Scalable Bloom filter (Almeida et al., 2007): a chain of `BloomFilter` stages where each
+/// new stage is `growth_factor` times larger and has a false positive rate `tightening_ratio`
+/// times smaller than the previous one. The first stage gets `p * (1 - tightening_ratio)`,
+/// so the compound rate stays below `p` however many stages are added.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "ScalableBloomFilterFields"))]
+pub struct ScalableBloomFilter {
+    false_positive_rate: f64,
+    growth_factor: u64,
+    tightening_ratio: f64,
+    seed: u64,
+    stages: Vec<Stage>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct ScalableBloomFilterFields {
+    false_positive_rate: f64,
+    growth_factor: u64,
+    tightening_ratio: f64,
+    seed: u64,
+    stages: Vec<Stage>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<ScalableBloomFilterFields> for ScalableBloomFilter {
+    type Error = Error;
+
+    fn try_from(fields: ScalableBloomFilterFields) -> Result<Self> {
+        let ScalableBloomFilterFields { false_positive_rate, growth_factor, tightening_ratio, seed, stages } = fields;
+        if stages.is_empty() {
+            return Err(Error::Corrupt("a scalable Bloom filter needs at least one stage"));
+        }
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(Error::Corrupt("false positive rate must be in (0, 1)"));
+        }
+        if growth_factor < 1 || !(tightening_ratio > 0.0 && tightening_ratio < 1.0) {
+            return Err(Error::Corrupt("growth factor or tightening ratio out of range"));
+        }
+        Ok(ScalableBloomFilter { false_positive_rate, growth_factor, tightening_ratio, seed, stages })
+    }
+}
+
+impl ScalableBloomFilter {
+    pub fn new(false_positive_rate: f64, initial_capacity: u64) -> Self {
+        ScalableBloomFilter::with_seed(rand::random(), false_positive_rate, initial_capacity)
+    }
+
+    pub fn with_seed(seed: u64, false_positive_rate: f64, initial_capacity: u64) -> Self {
+        ScalableBloomFilter::with_growth(seed, false_positive_rate, initial_capacity,
+                                         DEFAULT_GROWTH_FACTOR, DEFAULT_TIGHTENING_RATIO)
+    }
+
+    pub fn with_growth(seed: u64, false_positive_rate: f64, initial_capacity: u64,
+                       growth_factor: u64, tightening_ratio: f64) -> Self {
+        assert!(growth_factor >= 1, "growth factor must be at least 1");
+        assert!(tightening_ratio > 0.0 && tightening_ratio < 1.0, "tightening ratio must be in (0, 1)");
+
+        let mut filter = ScalableBloomFilter { false_positive_rate, growth_factor, tightening_ratio, seed, stages: vec![] };
+        filter.add_stage(initial_capacity.max(1));
+        filter
+    }
+
+    /// Puts `value` unless it is already (possibly falsely) reported as present, so repeated
+    /// puts do not use up capacity.
+    pub fn put<T: Hash>(&mut self, value: T) {
+        if self.contains(&value) {
+            return;
+        }
+
+        let last = self.stages.last().unwrap();
+        if last.len >= last.capacity {
+            let capacity = last.capacity.saturating_mul(self.growth_factor);
+            self.add_stage(capacity);
+        }
+
+        let stage = self.stages.last_mut().unwrap();
+        stage.filter.put(value);
+        stage.len += 1;
+    }
+
+    pub fn contains<T: Hash>(&self, value: T) -> bool {
+        self.stages.iter().any(|stage| stage.filter.contains(&value))
+    }
+
+    /// Number of distinct items put, as far as the filter could tell.
+    pub fn len(&self) -> u64 {
+        self.stages.iter().map(|stage| stage.len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn n_stages(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Number of items the stages were sized for, together.
+    pub fn capacity(&self) -> u64 {
+        self.stages.iter().map(|stage| stage.capacity).fold(0, u64::saturating_add)
+    }
+
+    /// Total bits used by all stages.
+    pub fn n_bits(&self) -> usize {
+        self.stages.iter().map(|stage| stage.filter.n_bits()).sum()
+    }
+
+    /// Expected compound false positive rate of the stages at their current fill.
+    pub fn current_false_positive_rate(&self) -> f64 {
+        1.0 - self.stages.iter()
+            .map(|stage| 1.0 - stage.filter.false_positive_rate_at(stage.len))
+            .product::<f64>()
+    }
+
+    fn add_stage(&mut self, capacity: u64) {
+        let index = self.stages.len() as i32;
+        let stage_rate = self.false_positive_rate * (1.0 - self.tightening_ratio) * self.tightening_ratio.powi(index);
+        let filter = BloomFilter::with_seed(self.seed.wrapping_add(index as u64), stage_rate, capacity);
+
+        self.stages.push(Stage { filter, capacity, len: 0 });
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scalable_bloom_filter_grows() {
+        let mut filter = ScalableBloomFilter::new(0.01, 100);
+        (0..10000u64).for_each(|n| filter.put(n));
+
+        assert!(filter.n_stages() > 1);
+        assert_eq!(filter.capacity(), 100 * ((1 << filter.n_stages()) - 1));
+        assert!(filter.len() <= 10000 && filter.len() > 9700);
+        assert!((0..10000u64).all(|n| filter.contains(n)));
+        assert!(filter.current_false_positive_rate() < 0.01);
+    }
+
+    #[test]
+    fn test_scalable_bloom_filter_bounded_false_positive_rate() {
+        let mut filter = ScalableBloomFilter::with_seed(3, 0.01, 1000);
+        (0..100000u64).for_each(|n| filter.put(n));
+
+        // 1% of the probes, plus room for sampling noise.
+        let false_positives = (100000..200000u64).filter(|&n| filter.contains(n)).count();
+        assert!(false_positives < 1100, "{}", false_positives);
+    }
+
+    #[test]
+    fn test_scalable_bloom_filter_ignores_repeated_puts() {
+        let mut filter = ScalableBloomFilter::new(0.01, 10);
+        (0..100).for_each(|_| filter.put("same"));
+
+        assert_eq!(filter.len(), 1);
+        assert_eq!(filter.n_stages(), 1);
+    }
+}
@@ -0,0 +1,16 @@
+//! `#[serde(with = ...)]` helpers for field types that do not implement serde themselves.
+
+pub mod bit_set {
+    use bit_set::BitSet;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde_bytes::ByteBuf;
+
+    pub fn serialize<S: Serializer>(bit_set: &BitSet, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&bit_set.get_ref().to_bytes())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BitSet, D::Error> {
+        let bytes = ByteBuf::deserialize(deserializer)?;
+        Ok(BitSet::from_bytes(&bytes))
+    }
+}
@@ -0,0 +1,215 @@
+use crate::error::{Error, Result};
+use crate::hash;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+
+
+/// Bytes per block: eight 32-bit words.
+pub const BLOCK_BYTES: usize = 32;
+/// Largest bitset the Parquet specification allows.
+pub const MAX_BYTES: usize = 128 * 1024 * 1024;
+const SALT: [u32; 8] = [
+    0x47b6_137b, 0x4497_4d91, 0x8824_ad5b, 0xa2b7_289d,
+    0x7054_95c7, 0x2df1_424b, 0x9efc_4947, 0x5c6b_fb31,
+];
+
+/// A value as Parquet hashes it for Bloom filters: xxHash64 with seed 0 over its plain
+/// encoding, i.e. little-endian bytes for numbers and the raw bytes, without the length
+/// prefix, for byte arrays.
+pub trait PlainEncoded {
+    fn plain_hash(&self) -> u64;
+}
+
+macro_rules! impl_plain_encoded_le {
+    ($($ty:ty),*) => {
+        $(impl PlainEncoded for $ty {
+            fn plain_hash(&self) -> u64 {
+                hash::xxhash64(&self.to_le_bytes(), 0)
+            }
+        })*
+    };
+}
+
+impl_plain_encoded_le!(i32, i64, u32, u64, f32, f64);
+
+impl PlainEncoded for [u8] {
+    fn plain_hash(&self) -> u64 {
+        hash::xxhash64(self, 0)
+    }
+}
+
+impl PlainEncoded for str {
+    fn plain_hash(&self) -> u64 {
+        hash::xxhash64(self.as_bytes(), 0)
+    }
+}
+
+/// Split block Bloom filter as specified for Parquet column chunks. The hash's upper 32 bits
+/// pick one 256-bit block, and its lower 32 bits, multiplied by eight fixed salts, set one bit
+/// in each of the block's eight 32-bit words.
+///
+/// `to_bytes` and `from_bytes` use the specification's bitset layout (blocks in order, words
+/// little-endian), which is what Parquet files store after the Bloom filter header.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SplitBlockBloomFilterFields"))]
+pub struct SplitBlockBloomFilter {
+    blocks: Vec<[u32; 8]>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct SplitBlockBloomFilterFields {
+    blocks: Vec<[u32; 8]>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SplitBlockBloomFilterFields> for SplitBlockBloomFilter {
+    type Error = Error;
+
+    fn try_from(fields: SplitBlockBloomFilterFields) -> Result<Self> {
+        let SplitBlockBloomFilterFields { blocks } = fields;
+        if blocks.is_empty() {
+            return Err(Error::Corrupt("a split block Bloom filter needs at least one block"));
+        }
+        Ok(SplitBlockBloomFilter { blocks })
+    }
+}
+
+impl SplitBlockBloomFilter {
+    /// Sized like the Parquet writers: `-8 * n / ln(1 - p^(1/8))` bits, rounded up to a power
+    /// of two bytes and clamped between one block and `MAX_BYTES`.
+    pub fn new(false_positive_rate: f64, expected_item_count: u64) -> Self {
+        assert!(false_positive_rate > 0.0 && false_positive_rate < 1.0, "false positive rate must be in (0, 1)");
+        let n_bits = -8.0 * expected_item_count as f64 / (1.0 - false_positive_rate.powf(1.0 / 8.0)).ln();
+        SplitBlockBloomFilter::with_num_bytes((n_bits / 8.0).ceil() as usize)
+    }
+
+    /// `num_bytes` is rounded up to a power of two and clamped between one block and
+    /// `MAX_BYTES`.
+    pub fn with_num_bytes(num_bytes: usize) -> Self {
+        let num_bytes = num_bytes.clamp(BLOCK_BYTES, MAX_BYTES).next_power_of_two();
+        SplitBlockBloomFilter { blocks: vec![[0; 8]; num_bytes / BLOCK_BYTES] }
+    }
+
+    /// Reads a bitset in the specification's layout.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.is_empty() || bytes.len() % BLOCK_BYTES != 0 {
+            return Err(Error::Corrupt("bitset is not a whole number of blocks"));
+        }
+
+        let blocks = bytes.chunks_exact(BLOCK_BYTES).map(|block| {
+            let mut words = [0u32; 8];
+            for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+                *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            }
+            words
+        }).collect();
+        Ok(SplitBlockBloomFilter { blocks })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.blocks.iter().flat_map(|block| block.iter()).flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    pub fn put<V: PlainEncoded + ?Sized>(&mut self, value: &V) {
+        self.put_hash(value.plain_hash());
+    }
+
+    pub fn contains<V: PlainEncoded + ?Sized>(&self, value: &V) -> bool {
+        self.contains_hash(value.plain_hash())
+    }
+
+    /// Inserts an already computed xxHash64, e.g. one taken from another Parquet library.
+    pub fn put_hash(&mut self, hash: u64) {
+        let index = self.block_index(hash);
+        for (word, mask) in self.blocks[index].iter_mut().zip(Self::mask(hash as u32).iter()) {
+            *word |= mask;
+        }
+    }
+
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        let block = &self.blocks[self.block_index(hash)];
+        block.iter().zip(Self::mask(hash as u32).iter()).all(|(word, mask)| word & mask != 0)
+    }
+
+    pub fn num_bytes(&self) -> usize {
+        self.blocks.len() * BLOCK_BYTES
+    }
+
+    fn block_index(&self, hash: u64) -> usize {
+        (((hash >> 32) * self.blocks.len() as u64) >> 32) as usize
+    }
+
+    fn mask(key: u32) -> [u32; 8] {
+        let mut mask = [0u32; 8];
+        for (bit, salt) in mask.iter_mut().zip(SALT.iter()) {
+            *bit = 1 << (key.wrapping_mul(*salt) >> 27);
+        }
+        mask
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_block_layout() {
+        let mut filter = SplitBlockBloomFilter::with_num_bytes(0);
+        assert_eq!(filter.num_bytes(), 32);
+
+        filter.put_hash(0x0123_4567_89ab_cdef);
+        assert_eq!(filter.to_bytes(), vec![
+            0, 0, 4, 0, 0, 0, 8, 0, 0, 0, 0, 64, 0, 0, 128, 0,
+            16, 0, 0, 0, 0, 0, 1, 0, 0, 16, 0, 0, 0, 0, 0, 1,
+        ]);
+        assert!(filter.contains_hash(0x0123_4567_89ab_cdef));
+    }
+
+    #[test]
+    fn test_split_block_parquet_mr_fixture() {
+        // Bitset written by parquet-mr (through Spark) for a string column holding "a0" to
+        // "a9", as checked in by arrow-rs in `parquet/src/bloom_filter/mod.rs`.
+        let bitset = [
+            200, 1, 80, 20, 64, 68, 8, 109, 6, 37, 4, 67, 144, 80, 96, 32,
+            8, 132, 43, 33, 0, 5, 99, 65, 2, 0, 224, 44, 64, 78, 96, 4,
+        ];
+        let read = SplitBlockBloomFilter::from_bytes(&bitset).unwrap();
+        assert!((0..10).all(|n| read.contains(format!("a{}", n).as_str())));
+
+        let mut filter = SplitBlockBloomFilter::with_num_bytes(32);
+        (0..10).for_each(|n| filter.put(format!("a{}", n).as_str()));
+        assert_eq!(filter.to_bytes(), bitset.to_vec());
+    }
+
+    #[test]
+    fn test_split_block_bloom_filter() {
+        let mut filter = SplitBlockBloomFilter::new(0.01, 10000);
+        assert_eq!(filter.num_bytes(), 16384);
+        (0..10000i64).for_each(|n| filter.put(&n));
+
+        let read = SplitBlockBloomFilter::from_bytes(&filter.to_bytes()).unwrap();
+        assert!((0..10000i64).all(|n| read.contains(&n)));
+        // Rounding up to 16 KiB leaves the rate at 0.35%: expect 354, sigma 19.
+        let false_positives = (10000..110000i64).filter(|n| read.contains(n)).count();
+        assert!(false_positives < 440, "{}", false_positives);
+
+        assert!(SplitBlockBloomFilter::from_bytes(&[0; 33]).is_err());
+        assert!(SplitBlockBloomFilter::from_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn test_split_block_plain_encoding() {
+        let mut filter = SplitBlockBloomFilter::with_num_bytes(1024);
+        filter.put("parquet");
+        filter.put(&7i32);
+
+        assert!(filter.contains(&b"parquet"[..]));
+        assert!(filter.contains_hash(hash::xxhash64(&7i32.to_le_bytes(), 0)));
+        assert_eq!(7u32.plain_hash(), 7i32.plain_hash());
+        assert_ne!(7i64.plain_hash(), 7i32.plain_hash());
+    }
+}
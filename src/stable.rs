@@ -0,0 +1,222 @@
+use crate::bloom::BloomFilter;
+use crate::fingerprint;
+#[cfg(feature = "serde")]
+use crate::error::{Error, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+
+pub const DEFAULT_CELL_BITS: u32 = 2;
+
+/// Stable Bloom filter (Deng & Rafiei, 2006) for unbounded streams. Each cell is a small
+/// counter: a put first decrements `P` cells starting at a random position, then sets the
+/// value's `k` cells to the maximum. Old items thereby fade out, and the fraction of non-zero
+/// cells converges so that the false positive rate settles at a configured bound instead of
+/// climbing to 1.
+///
+/// In exchange, a recently put item can be forgotten (a false negative) once enough later
+/// puts have decremented one of its cells back to zero.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "StableBloomFilterFields"))]
+pub struct StableBloomFilter {
+    n_hashes: u16,
+    n_cells: usize,
+    cell_bits: u32,
+    n_decrements: usize,
+    seed: u64,
+    rng_state: u64,
+    words: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct StableBloomFilterFields {
+    n_hashes: u16,
+    n_cells: usize,
+    cell_bits: u32,
+    n_decrements: usize,
+    seed: u64,
+    rng_state: u64,
+    words: Vec<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<StableBloomFilterFields> for StableBloomFilter {
+    type Error = Error;
+
+    fn try_from(fields: StableBloomFilterFields) -> Result<Self> {
+        let StableBloomFilterFields { n_hashes, n_cells, cell_bits, n_decrements, seed, rng_state, words } = fields;
+        if n_hashes == 0 || n_cells == 0 || !(1..=8).contains(&cell_bits) {
+            return Err(Error::Corrupt("stable Bloom filter dimensions out of range"));
+        }
+        if words.len() != n_cells.div_ceil((64 / cell_bits) as usize) {
+            return Err(Error::Corrupt("cell words do not match the cell count"));
+        }
+        Ok(StableBloomFilter { n_hashes, n_cells, cell_bits, n_decrements, seed, rng_state, words })
+    }
+}
+
+impl StableBloomFilter {
+    pub fn new(false_positive_rate: f64, n_cells: usize) -> Self {
+        StableBloomFilter::with_seed(rand::random(), false_positive_rate, n_cells)
+    }
+
+    pub fn with_seed(seed: u64, false_positive_rate: f64, n_cells: usize) -> Self {
+        StableBloomFilter::with_options(seed, false_positive_rate, n_cells, DEFAULT_CELL_BITS)
+    }
+
+    /// Uses `round(-log2(p))` hashes, and as many decrements per put as needed for the
+    /// stable false positive rate to be at most `false_positive_rate`.
+    pub fn with_options(seed: u64, false_positive_rate: f64, n_cells: usize, cell_bits: u32) -> Self {
+        assert!(false_positive_rate > 0.0 && false_positive_rate < 1.0, "false positive rate must be in (0, 1)");
+        assert!((1..=8).contains(&cell_bits), "cell bits must be in 1..=8");
+        assert!(n_cells > 0, "a stable Bloom filter needs at least one cell");
+
+        let n_hashes = (-false_positive_rate.log2()).round().max(1.0) as u16;
+        let max = ((1u32 << cell_bits) - 1) as f64;
+        // Solves the paper's stable point `(1 - (1 / (1 + 1 / (P (1/k - 1/m))))^Max)^k = p` for P.
+        let spread = 1.0 / n_hashes as f64 - 1.0 / n_cells as f64;
+        let fade = (1.0 - false_positive_rate.powf(1.0 / n_hashes as f64)).powf(-1.0 / max) - 1.0;
+        let n_decrements = (1.0 / (spread * fade)).ceil().clamp(1.0, n_cells as f64) as usize;
+
+        let per_word = (64 / cell_bits) as usize;
+        StableBloomFilter {
+            n_hashes,
+            n_cells,
+            cell_bits,
+            n_decrements,
+            seed,
+            rng_state: fingerprint::mix64(seed),
+            words: vec![0; n_cells.div_ceil(per_word)],
+        }
+    }
+
+    pub fn put<T: Hash>(&mut self, value: T) {
+        let start = ((self.next_random() as u128 * self.n_cells as u128) >> 64) as usize;
+        for i in 0..self.n_decrements {
+            let cell = (start + i) % self.n_cells;
+            let count = self.get(cell);
+            if count > 0 {
+                self.set(cell, count - 1);
+            }
+        }
+
+        let max = self.max_count();
+        for cell in BloomFilter::get_bits(self.seed, self.n_hashes, self.n_cells, &value) {
+            self.set(cell, max);
+        }
+    }
+
+    pub fn contains<T: Hash>(&self, value: T) -> bool {
+        BloomFilter::get_bits(self.seed, self.n_hashes, self.n_cells, &value).all(|cell| self.get(cell) > 0)
+    }
+
+    pub fn n_hashes(&self) -> u16 {
+        self.n_hashes
+    }
+
+    pub fn n_cells(&self) -> usize {
+        self.n_cells
+    }
+
+    pub fn cell_bits(&self) -> u32 {
+        self.cell_bits
+    }
+
+    /// Number of cells decremented by each put.
+    pub fn n_decrements(&self) -> usize {
+        self.n_decrements
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Fraction of cells that are non-zero.
+    pub fn fill_ratio(&self) -> f64 {
+        (0..self.n_cells).filter(|&cell| self.get(cell) > 0).count() as f64 / self.n_cells as f64
+    }
+
+    /// False positive rate at the current fill ratio.
+    pub fn current_false_positive_rate(&self) -> f64 {
+        self.fill_ratio().powi(self.n_hashes as i32)
+    }
+
+    /// The false positive rate the filter converges to as the stream goes on.
+    pub fn stable_false_positive_rate(&self) -> f64 {
+        let spread = 1.0 / self.n_hashes as f64 - 1.0 / self.n_cells as f64;
+        let zero_probability = (1.0 / (1.0 + 1.0 / (self.n_decrements as f64 * spread))).powi(self.max_count() as i32);
+        (1.0 - zero_probability).powi(self.n_hashes as i32)
+    }
+
+    fn max_count(&self) -> u8 {
+        ((1u32 << self.cell_bits) - 1) as u8
+    }
+
+    /// SplitMix64, kept in the filter so decrements are reproducible for a given seed.
+    fn next_random(&mut self) -> u64 {
+        self.rng_state = self.rng_state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        fingerprint::mix64(self.rng_state)
+    }
+
+    fn position(&self, cell: usize) -> (usize, u32) {
+        let per_word = (64 / self.cell_bits) as usize;
+        (cell / per_word, (cell % per_word) as u32 * self.cell_bits)
+    }
+
+    fn get(&self, cell: usize) -> u8 {
+        let (word, shift) = self.position(cell);
+        ((self.words[word] >> shift) & self.max_count() as u64) as u8
+    }
+
+    fn set(&mut self, cell: usize, count: u8) {
+        let (word, shift) = self.position(cell);
+        let mask = (self.max_count() as u64) << shift;
+        self.words[word] = (self.words[word] & !mask) | ((count as u64) << shift);
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stable_bloom_filter_parameters() {
+        let filter = StableBloomFilter::with_seed(1, 0.01, 100000);
+        assert_eq!(filter.n_hashes(), 7);
+        assert_eq!(filter.cell_bits(), DEFAULT_CELL_BITS);
+        assert!(filter.n_decrements() > 1);
+        let stable = filter.stable_false_positive_rate();
+        assert!(stable <= 0.01 && stable > 0.009, "{}", stable);
+    }
+
+    #[test]
+    fn test_stable_bloom_filter_converges() {
+        let mut filter = StableBloomFilter::with_seed(2, 0.02, 20000);
+        for n in 0..500000u64 {
+            filter.put(n);
+            assert!(filter.contains(n));
+        }
+
+        // At the fill reached with seed 2 the model gives 2.02%: about 2020 of these 100000
+        // absent keys, sigma 44.
+        let false_positives = (1000000..1100000u64).filter(|n| filter.contains(n)).count();
+        assert!(false_positives < 2200, "{}", false_positives);
+        assert!(filter.current_false_positive_rate() < 0.023);
+    }
+
+    #[test]
+    fn test_stable_bloom_filter_forgets_old_items() {
+        let mut filter = StableBloomFilter::with_options(3, 0.01, 10000, 3);
+        (0..100000u64).for_each(|n| filter.put(n));
+
+        let recent = (99900..100000u64).filter(|n| filter.contains(n)).count();
+        assert!(recent > 95, "{}", recent);
+        let remembered = (0..1000u64).filter(|n| filter.contains(n)).count();
+        assert!(remembered < 50, "{}", remembered);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::bloom::BloomFilter;
+#[cfg(feature = "serde")]
+use crate::error::{Error, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+/// Source of the current time for `SlidingWindowBloomFilter`, as a duration since an
+/// arbitrary fixed epoch. Closures returning a `Duration` are clocks too, which lets tests
+/// drive time by hand.
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+/// Wall-clock time since the Unix epoch.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+}
+
+impl<F: Fn() -> Duration> Clock for F {
+    fn now(&self) -> Duration {
+        self()
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct Generation {
+    started: Duration,
+    filter: BloomFilter,
+}
+
+/// "Seen in the last `window`" filter made of rotating `BloomFilter` generations, each
+/// covering `window / n_generations`. Puts go to the newest generation, and a generation
+/// expires once a whole `window` has passed since it stopped taking puts, so an item is
+/// remembered for at least `window` and at most `window + window / n_generations`.
+///
+/// Every generation gets `1 / (n_generations + 1)` of the false positive rate, as that many
+/// can be live at once. Generation `i` since the filter was made is seeded with `seed + i`, so
+/// an item's false positives in one generation are independent of those in the next.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SlidingWindowBloomFilterFields"))]
+pub struct SlidingWindowBloomFilter<C = SystemClock> {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: C,
+    seed: u64,
+    window: Duration,
+    generation_span: Duration,
+    generation_false_positive_rate: f64,
+    generation_capacity: u64,
+    generations: VecDeque<Generation>,
+    next_index: u64,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct SlidingWindowBloomFilterFields {
+    seed: u64,
+    window: Duration,
+    generation_span: Duration,
+    generation_false_positive_rate: f64,
+    generation_capacity: u64,
+    generations: VecDeque<Generation>,
+    next_index: u64,
+}
+
+#[cfg(feature = "serde")]
+impl<C: Default> TryFrom<SlidingWindowBloomFilterFields> for SlidingWindowBloomFilter<C> {
+    type Error = Error;
+
+    fn try_from(fields: SlidingWindowBloomFilterFields) -> Result<Self> {
+        let SlidingWindowBloomFilterFields {
+            seed, window, generation_span, generation_false_positive_rate, generation_capacity, generations, next_index,
+        } = fields;
+        if window == Duration::from_secs(0) || generation_span == Duration::from_secs(0) {
+            return Err(Error::Corrupt("window and generations must not be empty"));
+        }
+        if !(generation_false_positive_rate > 0.0 && generation_false_positive_rate < 1.0)
+            || generation_capacity == 0 {
+            return Err(Error::Corrupt("generation parameters out of range"));
+        }
+        Ok(SlidingWindowBloomFilter {
+            clock: C::default(),
+            seed,
+            window,
+            generation_span,
+            generation_false_positive_rate,
+            generation_capacity,
+            generations,
+            next_index,
+        })
+    }
+}
+
+impl SlidingWindowBloomFilter<SystemClock> {
+    /// Sized for `expected_item_count` puts per `window`, spread evenly over time.
+    pub fn new(false_positive_rate: f64, expected_item_count: u64, window: Duration, n_generations: u32) -> Self {
+        SlidingWindowBloomFilter::with_clock(SystemClock, rand::random(), false_positive_rate,
+                                             expected_item_count, window, n_generations)
+    }
+}
+
+impl<C: Clock> SlidingWindowBloomFilter<C> {
+    pub fn with_clock(clock: C, seed: u64, false_positive_rate: f64, expected_item_count: u64,
+                      window: Duration, n_generations: u32) -> Self {
+        assert!(n_generations >= 1, "a sliding window needs at least one generation");
+        let generation_span = window / n_generations;
+        assert!(generation_span > Duration::from_secs(0), "window is too short to split into n_generations");
+
+        SlidingWindowBloomFilter {
+            clock,
+            seed,
+            window,
+            generation_span,
+            generation_false_positive_rate: false_positive_rate / (n_generations + 1) as f64,
+            generation_capacity: expected_item_count.div_ceil(n_generations as u64).max(1),
+            generations: VecDeque::new(),
+            next_index: 0,
+        }
+    }
+
+    /// Advances to the clock's current time and puts `value` into the newest generation.
+    pub fn put<T: Hash>(&mut self, value: T) {
+        let now = self.clock.now();
+        self.advance(now);
+        self.generations.back_mut().unwrap().filter.put(value);
+    }
+
+    /// Checks every generation that has not expired by the clock's current time.
+    pub fn contains<T: Hash>(&self, value: T) -> bool {
+        let now = self.clock.now();
+        self.generations.iter().any(|generation| !self.is_expired(generation, now) && generation.filter.contains(&value))
+    }
+
+    /// Drops the generations expired at `now` and starts a new one if the newest is over
+    /// its span. Time going backwards is ignored.
+    pub fn advance(&mut self, now: Duration) {
+        while self.generations.front().is_some_and(|generation| self.is_expired(generation, now)) {
+            self.generations.pop_front();
+        }
+
+        if self.generations.back().map_or(true, |generation| now >= generation.started + self.generation_span) {
+            let seed = self.seed.wrapping_add(self.next_index);
+            let filter = BloomFilter::with_seed(seed, self.generation_false_positive_rate, self.generation_capacity);
+            self.generations.push_back(Generation { started: now, filter });
+            self.next_index += 1;
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Number of generations held, including any that expired since the last `advance`.
+    pub fn n_generations(&self) -> usize {
+        self.generations.len()
+    }
+
+    pub fn n_bits(&self) -> usize {
+        self.generations.iter().map(|generation| generation.filter.n_bits()).sum()
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Expected compound false positive rate of the live generations at their current fill.
+    pub fn current_false_positive_rate(&self) -> f64 {
+        let now = self.clock.now();
+        1.0 - self.generations.iter()
+            .filter(|generation| !self.is_expired(generation, now))
+            .map(|generation| 1.0 - generation.filter.current_false_positive_rate())
+            .product::<f64>()
+    }
+
+    fn is_expired(&self, generation: &Generation, now: Duration) -> bool {
+        generation.started + self.generation_span + self.window <= now
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn manual_clock() -> (Rc<Cell<Duration>>, impl Fn() -> Duration) {
+        let time = Rc::new(Cell::new(Duration::from_secs(1000)));
+        let clock_time = time.clone();
+        (time, move || clock_time.get())
+    }
+
+    #[test]
+    fn test_sliding_window_bloom_filter_expires() {
+        let (time, clock) = manual_clock();
+        let mut filter = SlidingWindowBloomFilter::with_clock(clock, 1, 0.01, 1000, Duration::from_secs(60), 6);
+
+        filter.put("early");
+        time.set(time.get() + Duration::from_secs(30));
+        filter.put("late");
+        assert!(filter.contains("early") && filter.contains("late"));
+
+        time.set(time.get() + Duration::from_secs(29));
+        assert!(filter.contains("early"));
+        time.set(time.get() + Duration::from_secs(11));
+        assert!(!filter.contains("early"));
+        assert!(filter.contains("late"));
+
+        time.set(time.get() + Duration::from_secs(40));
+        assert!(!filter.contains("late"));
+        assert_eq!(filter.n_generations(), 2);
+        filter.advance(time.get());
+        assert_eq!(filter.n_generations(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "window is too short")]
+    fn test_sliding_window_bloom_filter_rejects_empty_generations() {
+        SlidingWindowBloomFilter::new(0.01, 1000, Duration::from_nanos(3), 4);
+    }
+
+    #[test]
+    fn test_sliding_window_bloom_filter_seeds_each_generation() {
+        let (time, clock) = manual_clock();
+        let mut filter = SlidingWindowBloomFilter::with_clock(clock, u64::MAX, 0.01, 1000, Duration::from_secs(60), 2);
+        for _ in 0..3 {
+            filter.put("item");
+            time.set(time.get() + Duration::from_secs(30));
+        }
+        let seeds: Vec<u64> = filter.generations.iter().map(|generation| generation.filter.seed()).collect();
+        assert_eq!(seeds, [u64::MAX, 0, 1]);
+    }
+
+    #[test]
+    fn test_sliding_window_bloom_filter_accuracy() {
+        let (time, clock) = manual_clock();
+        let mut filter = SlidingWindowBloomFilter::with_clock(clock, 2, 0.01, 60000, Duration::from_secs(60), 4);
+
+        for n in 0..180000u64 {
+            filter.put(n);
+            time.set(time.get() + Duration::from_millis(1));
+        }
+
+        assert!((120000..180000u64).all(|n| filter.contains(n)));
+        assert!(filter.n_generations() <= 5);
+        // The live generations are not all full, so expect about 0.8% (800, sigma 28) rather
+        // than the 1% the filter was sized for.
+        let false_positives = (1000000..1100000u64).filter(|n| filter.contains(n)).count();
+        assert!(false_positives < 1000, "{}", false_positives);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::error::{Error, Result};
+use crate::fingerprint::{self, Fingerprint};
+use crate::hash;
+use crate::peeling;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::hash::Hash;
+
+
+const MAX_ATTEMPTS: usize = 100;
+
+pub type XorFilter8 = XorFilter<u8>;
+pub type XorFilter16 = XorFilter<u16>;
+
+/// Immutable xor filter (Graf & Lemire, 2020) over a fixed key set. Every key maps to one
+/// slot in each of three blocks, and the fingerprints are assigned so that the three slots
+/// of a key xor to its fingerprint. Takes about `1.23 * F::BITS` bits per key.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "XorFilterFields<F>"))]
+pub struct XorFilter<F> {
+    seed: u64,
+    mix_seed: u64,
+    block_length: u32,
+    len: usize,
+    fingerprints: Vec<F>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct XorFilterFields<F> {
+    seed: u64,
+    mix_seed: u64,
+    block_length: u32,
+    len: usize,
+    fingerprints: Vec<F>,
+}
+
+#[cfg(feature = "serde")]
+impl<F> TryFrom<XorFilterFields<F>> for XorFilter<F> {
+    type Error = Error;
+
+    fn try_from(fields: XorFilterFields<F>) -> Result<Self> {
+        let XorFilterFields { seed, mix_seed, block_length, len, fingerprints } = fields;
+        if block_length == 0 || fingerprints.len() != 3 * block_length as usize {
+            return Err(Error::Corrupt("fingerprint count does not match the block length"));
+        }
+        Ok(XorFilter { seed, mix_seed, block_length, len, fingerprints })
+    }
+}
+
+impl<F: Fingerprint> XorFilter<F> {
+    pub fn build<I>(keys: I) -> Result<Self> where I: IntoIterator, I::Item: Hash {
+        XorFilter::build_with_seed(rand::random(), keys)
+    }
+
+    /// Builds the filter, retrying with a fresh mixing seed whenever the key graph cannot
+    /// be peeled. Duplicate keys are ignored.
+    pub fn build_with_seed<I>(seed: u64, keys: I) -> Result<Self> where I: IntoIterator, I::Item: Hash {
+        let mut hashes: Vec<u64> = keys.into_iter().map(|key| hash::hash128(&key, seed).0).collect();
+        hashes.sort_unstable();
+        hashes.dedup();
+
+        let capacity = 32 + (1.23 * hashes.len() as f64).ceil() as usize;
+        let block_length = (capacity / 3) as u32;
+        let n_slots = 3 * block_length as usize;
+        let mut mix_seed = seed;
+
+        for _ in 0..MAX_ATTEMPTS {
+            mix_seed = fingerprint::mix64(mix_seed.wrapping_add(0x9e37_79b9_7f4a_7c15));
+            let mut filter = XorFilter { seed, mix_seed, block_length, len: hashes.len(), fingerprints: vec![] };
+            let mixed: Vec<u64> = hashes.iter().map(|&hash| filter.mix(hash)).collect();
+            if let Some(order) = peeling::peel(&mixed, n_slots, |hash| filter.slots(hash)) {
+                filter.fingerprints = peeling::assign(order, n_slots, |hash| filter.slots(hash));
+                return Ok(filter);
+            }
+        }
+        Err(Error::BuildFailed("key graph could not be peeled"))
+    }
+
+    pub fn contains<T: Hash>(&self, value: T) -> bool {
+        let hash = self.mix(hash::hash128(&value, self.seed).0);
+        let [h0, h1, h2] = self.slots(hash);
+
+        F::from_hash(hash) == self.fingerprints[h0] ^ self.fingerprints[h1] ^ self.fingerprints[h2]
+    }
+
+    /// Number of distinct keys the filter was built from.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn n_fingerprints(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    pub fn bits_per_key(&self) -> f64 {
+        (self.fingerprints.len() as u64 * F::BITS as u64) as f64 / self.len.max(1) as f64
+    }
+
+    fn mix(&self, key_hash: u64) -> u64 {
+        fingerprint::mix64(key_hash.wrapping_add(self.mix_seed))
+    }
+
+    fn slots(&self, hash: u64) -> [usize; 3] {
+        let block_length = self.block_length;
+        [
+            fingerprint::reduce(hash as u32, block_length) as usize,
+            (fingerprint::reduce(hash.rotate_left(21) as u32, block_length) + block_length) as usize,
+            (fingerprint::reduce(hash.rotate_left(42) as u32, block_length) + 2 * block_length) as usize,
+        ]
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xor_filter8() {
+        let keys: Vec<u64> = (0..100000).collect();
+        let filter = XorFilter8::build(&keys).unwrap();
+
+        assert_eq!(filter.len(), 100000);
+        assert!(filter.bits_per_key() < 10.0);
+        assert!(keys.iter().all(|key| filter.contains(key)));
+        let false_positives = (100000..200000u64).filter(|n| filter.contains(n)).count();
+        assert!(false_positives < 600, "{}", false_positives);
+    }
+
+    #[test]
+    fn test_xor_filter16_with_duplicates() {
+        let keys = (0..50000u64).chain(0..50000u64);
+        let filter = XorFilter16::build_with_seed(5, keys).unwrap();
+
+        assert_eq!(filter.len(), 50000);
+        assert!((0..50000u64).all(|key| filter.contains(key)));
+        let false_positives = (50000..150000u64).filter(|n| filter.contains(n)).count();
+        assert!(false_positives < 10, "{}", false_positives);
+    }
+
+    #[test]
+    fn test_xor_filter_small_sets() {
+        let empty = XorFilter8::build(Vec::<u64>::new()).unwrap();
+        assert!(empty.is_empty());
+
+        let filter = XorFilter8::build(vec!["a", "b", "c"]).unwrap();
+        assert!(filter.contains("a") && filter.contains("b") && filter.contains("c"));
+    }
+}
@@ -0,0 +1,39 @@
+import com.google.common.hash.BloomFilter;
+import com.google.common.hash.Funnels;
+import java.io.FileOutputStream;
+import java.io.IOException;
+import java.io.OutputStream;
+import java.nio.charset.StandardCharsets;
+
+/**
+ * Writes the Guava Bloom filter fixtures used by src/guava.rs with Guava's own
+ * {@code BloomFilter}, whose default strategy is MURMUR128_MITZ_64.
+ *
+ * <p>Run from this directory against Guava 33.3.1-jre:
+ *
+ * <pre>
+ * curl -O https://repo1.maven.org/maven2/com/google/guava/guava/33.3.1-jre/guava-33.3.1-jre.jar
+ * javac -cp guava-33.3.1-jre.jar GenerateFixtures.java
+ * java -cp guava-33.3.1-jre.jar:. GenerateFixtures
+ * </pre>
+ */
+public class GenerateFixtures {
+    public static void main(String[] args) throws IOException {
+        BloomFilter<CharSequence> strings = BloomFilter.create(Funnels.stringFunnel(StandardCharsets.UTF_8), 1000, 0.01);
+        for (int i = 0; i < 1000; i++) {
+            strings.put("key-" + i);
+        }
+        strings.put("na\u00efve");
+        try (OutputStream out = new FileOutputStream("strings.bin")) {
+            strings.writeTo(out);
+        }
+
+        BloomFilter<Long> longs = BloomFilter.create(Funnels.longFunnel(), 500, 0.001);
+        for (long i = 0; i < 500; i++) {
+            longs.put(i * 7);
+        }
+        try (OutputStream out = new FileOutputStream("longs.bin")) {
+            longs.writeTo(out);
+        }
+    }
+}
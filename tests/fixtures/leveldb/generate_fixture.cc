@@ -0,0 +1,28 @@
+// Writes bloom.bin, the LevelDB filter fixture used by src/leveldb.rs, with LevelDB's own
+// NewBloomFilterPolicy(10) over the keys "key-0" to "key-999".
+//
+// Built against a LevelDB 1.22 source tree:
+//
+//   g++ -std=c++17 -DLEVELDB_PLATFORM_POSIX -DLEVELDB_IS_BIG_ENDIAN=0 \
+//       -I$LEVELDB -I$LEVELDB/include generate_fixture.cc \
+//       $LEVELDB/util/bloom.cc $LEVELDB/util/hash.cc $LEVELDB/util/filter_policy.cc \
+//       -o generate_fixture && ./generate_fixture > bloom.bin
+
+#include <cstdio>
+#include <string>
+#include <vector>
+
+#include "leveldb/filter_policy.h"
+#include "leveldb/slice.h"
+
+int main() {
+  std::vector<std::string> keys;
+  for (int i = 0; i < 1000; i++) keys.push_back("key-" + std::to_string(i));
+  std::vector<leveldb::Slice> slices(keys.begin(), keys.end());
+
+  const leveldb::FilterPolicy* policy = leveldb::NewBloomFilterPolicy(10);
+  std::string filter;
+  policy->CreateFilter(slices.data(), static_cast<int>(slices.size()), &filter);
+  fwrite(filter.data(), 1, filter.size(), stdout);
+  delete policy;
+}
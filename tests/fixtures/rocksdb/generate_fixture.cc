@@ -0,0 +1,43 @@
+// Writes the RocksDB filter fixtures used by src/rocksdb.rs with the builder that
+// NewBloomFilterPolicy(10) hands a format_version=5 table: fast_local_bloom.bin holds the full
+// filter block for the keys "key-0" to "key-999", and empty.bin the one for no keys.
+//
+// FilterBitsBuilder's AddKey and Finish are declared in filter_policy_internal.h, so this
+// builds against a RocksDB 8.10 source tree after `make static_lib` there:
+//
+//   g++ -std=c++17 -I$ROCKSDB -I$ROCKSDB/include generate_fixture.cc $ROCKSDB/librocksdb.a \
+//       -lpthread -ldl -o generate_fixture && ./generate_fixture
+
+#include <cstdio>
+#include <memory>
+#include <string>
+
+#include "rocksdb/filter_policy.h"
+#include "rocksdb/table.h"
+#include "table/block_based/filter_policy_internal.h"
+
+using namespace ROCKSDB_NAMESPACE;
+
+static void WriteFilter(const char* path, int num_keys) {
+  BlockBasedTableOptions table_options;
+  table_options.format_version = 5;
+  table_options.optimize_filters_for_memory = false;
+  std::unique_ptr<const FilterPolicy> policy(NewBloomFilterPolicy(10));
+  std::unique_ptr<FilterBitsBuilder> builder(
+      policy->GetBuilderWithContext(FilterBuildingContext(table_options)));
+
+  for (int i = 0; i < num_keys; i++) {
+    builder->AddKey("key-" + std::to_string(i));
+  }
+  std::unique_ptr<const char[]> buf;
+  Slice filter = builder->Finish(&buf);
+
+  FILE* out = fopen(path, "wb");
+  fwrite(filter.data(), 1, filter.size(), out);
+  fclose(out);
+}
+
+int main() {
+  WriteFilter("fast_local_bloom.bin", 1000);
+  WriteFilter("empty.bin", 0);
+}